@@ -0,0 +1,151 @@
+//! Procedural macros embedding brainfuck programs in Rust code.
+
+use brainfuck::{compiler, optimizer, parser};
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+const DEFAULT_MEMORY: usize = 4096;
+
+/// Compiles a brainfuck program given as a string literal into a Rust function.
+///
+/// The macro evaluates to a function with the signature
+/// `fn run<R: Read, W: Write>(input: &mut R, output: &mut W) -> Result<(), Error>`, the program
+/// being parsed and optimized at compile time. A program which doesn't parse is reported as a
+/// compilation error pointing at the literal.
+///
+/// ```ignore
+/// let double = brainfuck_macros::brainfuck!(",[->++<]>.");
+/// let mut output = Vec::new();
+/// double(&mut &[21u8][..], &mut output).unwrap();
+/// assert_eq!(output, vec![42]);
+/// ```
+#[proc_macro]
+pub fn brainfuck(input: TokenStream) -> TokenStream {
+    match expand(input) {
+        Ok(expansion) => expansion,
+        Err((message, span)) => compile_error(&message, span),
+    }
+}
+
+fn expand(input: TokenStream) -> Result<TokenStream, (String, Span)> {
+    let literal = single_literal(input)?;
+    let span = literal.span();
+    let source = unquote(&literal.to_string())
+        .ok_or_else(|| ("expected a string literal".to_string(), span))?;
+    let ast = parser::parse(&source).map_err(|err| (err.to_string(), span))?;
+    let ast = optimizer::shrink_calls(&ast);
+
+    let mut out = Vec::new();
+    compiler::rust::generate_expression(DEFAULT_MEMORY, &ast, &mut out)
+        .expect("failed to generate the program");
+    let code = String::from_utf8(out).expect("generated code is not valid UTF-8");
+
+    Ok(code.parse().expect("generated code is not valid Rust"))
+}
+
+fn single_literal(input: TokenStream) -> Result<Literal, (String, Span)> {
+    let mut tokens = input.into_iter();
+    let literal = match tokens.next() {
+        Some(TokenTree::Literal(literal)) => literal,
+        // Literals forwarded by `macro_rules!` are wrapped in an invisible group.
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::None => {
+            return single_literal(group.stream());
+        }
+        Some(other) => return Err(("expected a string literal".to_string(), other.span())),
+        None => return Err(("expected a string literal".to_string(), Span::call_site())),
+    };
+    match tokens.next() {
+        Some(extra) => Err((
+            "unexpected token after the program".to_string(),
+            extra.span(),
+        )),
+        None => Ok(literal),
+    }
+}
+
+/// Returns the content of a string literal as written in the source code, or `None` if it's not
+/// a string literal.
+fn unquote(literal: &str) -> Option<String> {
+    if let Some(raw) = literal.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let delimiter = "#".repeat(hashes);
+        return raw
+            .strip_prefix(&delimiter)?
+            .strip_prefix('"')?
+            .strip_suffix(&delimiter)?
+            .strip_suffix('"')
+            .map(String::from);
+    }
+
+    let content = literal.strip_prefix('"')?.strip_suffix('"')?;
+    let mut result = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => result.push('\n'),
+            'r' => result.push('\r'),
+            't' => result.push('\t'),
+            '0' => result.push('\0'),
+            '\\' => result.push('\\'),
+            '\'' => result.push('\''),
+            '"' => result.push('"'),
+            'x' => {
+                let code: String = chars.by_ref().take(2).collect();
+                result.push(u8::from_str_radix(&code, 16).ok()? as char);
+            }
+            'u' => {
+                if chars.next()? != '{' {
+                    return None;
+                }
+                let code: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                result.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+            }
+            '\n' => {
+                // A line continuation also skips the indentation of the next line.
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            }
+            _ => return None,
+        }
+    }
+
+    Some(result)
+}
+
+fn compile_error(message: &str, span: Span) -> TokenStream {
+    let mut message = Literal::string(message);
+    message.set_span(span);
+    let mut bang = Punct::new('!', Spacing::Alone);
+    bang.set_span(span);
+    let mut arguments = Group::new(Delimiter::Parenthesis, TokenTree::from(message).into());
+    arguments.set_span(span);
+
+    [
+        TokenTree::from(Ident::new("compile_error", span)),
+        bang.into(),
+        arguments.into(),
+    ]
+    .into_iter()
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unquote_string_literals() {
+        assert_eq!(unquote(r#""++[>+<-].""#).as_deref(), Some("++[>+<-]."));
+        assert_eq!(
+            unquote(r#""+\n\"-\\\x2B\u{2d}""#).as_deref(),
+            Some("+\n\"-\\+-")
+        );
+        assert_eq!(unquote("\"+\\\n    -\"").as_deref(), Some("+-"));
+        assert_eq!(unquote(r###"r#"+"-"#"###).as_deref(), Some("+\"-"));
+        assert_eq!(unquote("r\"+\"").as_deref(), Some("+"));
+        assert_eq!(unquote("42"), None);
+        assert_eq!(unquote("'+'"), None);
+    }
+}
@@ -0,0 +1,29 @@
+use brainfuck_macros::brainfuck;
+
+#[test]
+fn run_embedded_program() {
+    let hello = brainfuck!(
+        "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++."
+    );
+    let mut output = Vec::new();
+    hello(&mut std::io::empty(), &mut output).unwrap();
+    assert_eq!(output, b"Hello World!\n");
+}
+
+#[test]
+fn read_input() {
+    let echo = brainfuck!(r",[.,]");
+    let mut output = Vec::new();
+    echo(&mut &b"echo\0"[..], &mut output).unwrap();
+    assert_eq!(output, b"echo");
+}
+
+#[test]
+fn report_execution_errors() {
+    let underflow = brainfuck!("<");
+    let err = underflow(&mut std::io::empty(), &mut Vec::new()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "failed during execution: negative memory address are invalid"
+    );
+}
@@ -0,0 +1,5 @@
+#[test]
+fn report_invalid_programs() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
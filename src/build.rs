@@ -0,0 +1,240 @@
+//! Helpers compiling brainfuck programs from a `build.rs` script.
+//!
+//! ```ignore
+//! // build.rs
+//! fn main() {
+//!     brainfuck::build::compile_dir("brainfuck").unwrap();
+//! }
+//!
+//! // src/lib.rs
+//! include!(concat!(env!("OUT_DIR"), "/brainfuck.rs"));
+//!
+//! pub fn greet() {
+//!     hello_world::run(&mut std::io::stdin(), &mut std::io::stdout()).unwrap();
+//! }
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use crate::encoding::Encoding;
+use crate::{compiler, optimizer, parser};
+
+/// Name of the index file declaring one module per compiled program.
+pub const INDEX: &str = "brainfuck.rs";
+
+const DEFAULT_MEMORY: usize = 4096;
+
+/// Keywords of Rust, reserved ones included, which can't name a module.
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+    "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+#[derive(Debug)]
+pub struct BuildError {
+    path: PathBuf,
+    message: String,
+}
+
+impl BuildError {
+    fn new(path: &Path, err: impl std::fmt::Display) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        }
+    }
+
+    /// File which couldn't be compiled.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Compiles every `.brainfuck` file of `dir` into the `OUT_DIR` of the running build script, and
+/// asks cargo to run it again whenever one of them changes.
+pub fn compile_dir<P: AsRef<Path>>(dir: P) -> Result<(), BuildError> {
+    let out_dir = std::env::var_os("OUT_DIR").expect("OUT_DIR is only set for build scripts");
+    println!("cargo:rerun-if-changed={}", dir.as_ref().display());
+    for source in compile_dir_to(dir, out_dir)? {
+        println!("cargo:rerun-if-changed={}", source.display());
+    }
+
+    Ok(())
+}
+
+/// Compiles every `.brainfuck` file of `dir` into a Rust module written in `out_dir`, along with
+/// an index declaring a module named after each program, and returns the compiled files.
+///
+/// Each module exposes the `run` function generated by `compiler::rust::generate_library`,
+/// writing raw bytes.
+pub fn compile_dir_to<P: AsRef<Path>, Q: AsRef<Path>>(
+    dir: P,
+    out_dir: Q,
+) -> Result<Vec<PathBuf>, BuildError> {
+    let dir = dir.as_ref();
+    let out_dir = out_dir.as_ref();
+    let mut sources = std::fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<Vec<_>>>()
+        })
+        .map_err(|err| BuildError::new(dir, err))?;
+    sources.retain(|path| path.extension().is_some_and(|ext| ext == "brainfuck"));
+    sources.sort();
+
+    let mut index = String::new();
+    let mut names = std::collections::HashMap::new();
+    for source in &sources {
+        let name = module_name(source);
+        if let Some(previous) = names.insert(name.clone(), source) {
+            return Err(BuildError::new(
+                source,
+                format!(
+                    "module {} is already compiled from {}",
+                    name,
+                    previous.display()
+                ),
+            ));
+        }
+        let module = out_dir.join(format!("{}.rs", name));
+        compile_file(source, &module)?;
+        index.push_str(&format!(
+            "pub mod {} {{\n    include!({:?});\n}}\n",
+            name,
+            module.display().to_string()
+        ));
+    }
+    let index_path = out_dir.join(INDEX);
+    std::fs::write(&index_path, index).map_err(|err| BuildError::new(&index_path, err))?;
+
+    Ok(sources)
+}
+
+fn compile_file(source: &Path, module: &Path) -> Result<(), BuildError> {
+    let code = std::fs::read_to_string(source).map_err(|err| BuildError::new(source, err))?;
+    let ast = parser::parse(&code).map_err(|err| BuildError::new(source, err))?;
+    let ast = optimizer::shrink_calls(&ast);
+    let mut out = std::fs::File::create(module).map_err(|err| BuildError::new(module, err))?;
+    compiler::rust::generate_library(
+        DEFAULT_MEMORY,
+        Encoding::Raw,
+        compiler::rust::InputFormat::Bytes,
+        &ast,
+        &mut out,
+    )
+    .map_err(|err| BuildError::new(module, err))
+}
+
+/// Turns the file name of a program into a valid module name, e.g. `tic-tac-toe` into
+/// `tic_tac_toe`, or `loop` into `loop_`.
+fn module_name(source: &Path) -> String {
+    let stem = source
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let name: String = stem
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let mut name = if name.starts_with(|c: char| c.is_ascii_digit()) || name.is_empty() {
+        format!("_{}", name)
+    } else {
+        name
+    };
+    if name == "_" || KEYWORDS.contains(&name.as_str()) {
+        name.push('_');
+    }
+
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_module_names() {
+        assert_eq!(
+            "tic_tac_toe",
+            module_name(Path::new("tic-tac-toe.brainfuck"))
+        );
+        assert_eq!(
+            "_99_bottles",
+            module_name(Path::new("dir/99 Bottles.brainfuck"))
+        );
+        assert_eq!("loop_", module_name(Path::new("loop.brainfuck")));
+        assert_eq!("__", module_name(Path::new("-.brainfuck")));
+    }
+
+    #[test]
+    fn report_conflicting_module_names() {
+        let dir = std::env::temp_dir().join(format!("brainfuck-build-dup-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a-b.brainfuck"), "+").unwrap();
+        std::fs::write(dir.join("a_b.brainfuck"), "-").unwrap();
+
+        let err = compile_dir_to(&dir, &dir).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(dir.join("a_b.brainfuck"), err.path());
+        assert!(err.to_string().ends_with(&format!(
+            "module a_b is already compiled from {}",
+            dir.join("a-b.brainfuck").display()
+        )));
+    }
+
+    #[test]
+    fn compile_examples() {
+        let dir = std::env::temp_dir().join(format!("brainfuck-build-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sources = compile_dir_to("examples", &dir).unwrap();
+        let main = dir.join("main.rs");
+        std::fs::write(
+            &main,
+            format!(
+                "#![deny(warnings)]\n\ninclude!({:?});\n\nfn main() {{\n    hello_world::run(&mut std::io::empty(), &mut std::io::stdout()).unwrap();\n    let _ = (rot13::run::<std::io::Empty, std::io::Sink>, tic_tac_toe::run::<std::io::Empty, std::io::Sink>);\n}}\n",
+                dir.join(INDEX).display().to_string()
+            ),
+        )
+        .unwrap();
+        let dest = dir.join("main");
+
+        compiler::rust::compile(main.to_str().unwrap(), dest.to_str().unwrap()).unwrap();
+
+        let output = std::process::Command::new(&dest).output().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(3, sources.len());
+        assert_eq!("Hello World!\n", String::from_utf8(output.stdout).unwrap());
+    }
+
+    #[test]
+    fn report_invalid_programs() {
+        let dir = std::env::temp_dir().join(format!("brainfuck-build-err-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("broken.brainfuck"), "[+").unwrap();
+
+        let err = compile_dir_to(&dir, &dir).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(dir.join("broken.brainfuck"), err.path());
+        assert!(err.to_string().ends_with("missing closing bracket"));
+    }
+}
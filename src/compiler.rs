@@ -1,132 +1,519 @@
-pub mod rust {
-    use crate::parser;
-    use askama::Template;
+use crate::encoding::Encoding;
+use crate::parser;
 
-    #[derive(Debug)]
-    pub struct CompilationError(&'static str);
+pub mod asm;
+pub mod bf;
+pub mod c;
+pub mod elf;
+pub mod js;
+pub mod llvm;
+pub mod python;
+pub mod rust;
+pub mod wasm;
 
-    impl std::error::Error for CompilationError {}
+#[derive(Debug)]
+pub struct CompilationError {
+    message: String,
+    code: Option<i32>,
+    diagnostics: String,
+}
 
-    impl std::fmt::Display for CompilationError {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            write!(f, "{}", self.0)
+impl CompilationError {
+    fn new<S: Into<String>>(message: S) -> Self {
+        CompilationError {
+            message: message.into(),
+            code: None,
+            diagnostics: String::new(),
         }
     }
 
-    #[derive(Template)]
-    #[template(path = "program.rs.txt")]
-    struct ProgramTemplate<'a> {
-        program: &'a Program,
+    /// Exit code of the toolchain when it ran and failed.
+    pub fn code(&self) -> Option<i32> {
+        self.code
     }
 
-    struct Program {
-        default_memory: usize,
-        read_buffer_definition: bool,
-        body: Vec<String>,
+    /// Errors reported by the toolchain on its standard error.
+    pub fn diagnostics(&self) -> &str {
+        &self.diagnostics
     }
+}
 
-    impl Program {
-        pub fn new(default_memory: usize) -> Self {
-            Program {
-                default_memory,
-                read_buffer_definition: false,
-                body: Vec::new(),
-            }
+impl std::error::Error for CompilationError {}
+
+impl std::fmt::Display for CompilationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(code) = self.code {
+            write!(f, " (exit code {})", code)?;
+        }
+        if !self.diagnostics.is_empty() {
+            write!(f, "\n{}", self.diagnostics.trim_end())?;
         }
+
+        Ok(())
     }
+}
 
-    pub fn compile(src: &str, dest: &str) -> Result<(), CompilationError> {
-        std::process::Command::new("rustc")
-            .arg("-o")
-            .arg(dest)
-            .arg(src)
-            .status()
-            .map_err(|_| CompilationError("failed to compile brainfuck Rust generated program"))?;
+/// Runs a toolchain program, turning its failure into a `CompilationError` carrying its exit
+/// code and what it reported on its standard error.
+fn run_toolchain(
+    command: &mut std::process::Command,
+    message: &'static str,
+) -> Result<(), CompilationError> {
+    let output = command.output().map_err(|err| {
+        CompilationError::new(format!(
+            "failed to run {}: {}",
+            command.get_program().to_string_lossy(),
+            err
+        ))
+    })?;
 
-        Ok(())
+    if !output.status.success() {
+        return Err(CompilationError {
+            message: message.to_string(),
+            code: output.status.code(),
+            diagnostics: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
     }
 
-    pub fn generate<W: std::io::Write>(
+    Ok(())
+}
+
+/// Whether `program` can be run, for the tests to skip the backends whose toolchain or runtime
+/// isn't installed.
+#[cfg(test)]
+fn tool_available(program: &str) -> bool {
+    std::process::Command::new(program)
+        .arg("--version")
+        .output()
+        .is_ok()
+}
+
+/// Program printing `Hello World!`, run by the tests of each backend.
+#[cfg(test)]
+const HELLO_WORLD: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+/// Temporary directory of a test, removed once the test is done.
+#[cfg(test)]
+struct TestDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("brainfuck-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        TestDir(dir)
+    }
+
+    fn join(&self, file: &str) -> std::path::PathBuf {
+        self.0.join(file)
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Generates `HELLO_WORLD` with `backend` and runs the command returned by `run`, which is given
+/// the temporary directory and the generated file. Returns what the command printed.
+#[cfg(test)]
+fn run_hello_world<F>(backend: &dyn Backend, run: F) -> String
+where
+    F: FnOnce(&TestDir, &std::path::Path) -> std::process::Command,
+{
+    let ast = parser::parse(HELLO_WORLD).unwrap();
+    let dir = TestDir::new(backend.name());
+    let src = dir.join(&format!("hello_world.{}", backend.extension()));
+    backend
+        .generate(
+            4,
+            Encoding::Raw,
+            &ast,
+            &mut std::fs::File::create(&src).unwrap(),
+        )
+        .unwrap();
+
+    let output = run(&dir, &src).output().unwrap();
+    assert!(output.status.success(), "backend {}", backend.name());
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Builds `HELLO_WORLD` into an executable with `backend` and runs it, returning what it printed.
+#[cfg(test)]
+fn build_hello_world(backend: &dyn Backend) -> String {
+    run_hello_world(backend, |dir, src| {
+        let dest = dir.join("hello_world");
+        backend
+            .build(src.to_str().unwrap(), dest.to_str().unwrap())
+            .unwrap();
+
+        std::process::Command::new(dest)
+    })
+}
+
+/// A target the brainfuck programs can be compiled to.
+pub trait Backend {
+    /// Name used to select the backend from the command line.
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    /// Extension of the file written by `generate`.
+    fn extension(&self) -> &'static str;
+
+    /// External programs required by `build`.
+    fn toolchain(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Generates the program, its output being written with `encoding`.
+    fn generate(
+        &self,
         default_memory: usize,
+        encoding: Encoding,
         ast: &[parser::Instruction],
-        out: &mut W,
-    ) -> std::io::Result<()> {
-        let mut program = Program::new(default_memory);
-        do_generate(&mut program, 2, ast);
-        write_program(&program, out)
-    }
-
-    fn write_program<W: std::io::Write>(program: &Program, out: &mut W) -> std::io::Result<()> {
-        let tmpl = ProgramTemplate { program };
-        println!("{}", tmpl.render().unwrap());
-
-        writeln!(out, "{}", tmpl.render().unwrap())
-    }
-
-    fn do_generate(program: &mut Program, spacing: usize, ast: &[parser::Instruction]) {
-        for instruction in ast {
-            match instruction {
-                parser::Instruction::MovePointerRight(n) => program
-                    .body
-                    .push(format!("mem.move_to_cell(mem.index + {});", n)),
-                parser::Instruction::MovePointerLeft(n) => program
-                    .body
-                    .push(format!("mem.move_to_cell(mem.index - {});", n)),
-                parser::Instruction::IncrementCell(n) => {
-                    program.body.push(format!("mem.increment_cell({});", n))
+        out: &mut dyn std::io::Write,
+    ) -> std::io::Result<()>;
+
+    /// Whether `build` turns the generated file into an executable. When it doesn't, the
+    /// generated file is the final artifact.
+    fn builds_executable(&self) -> bool {
+        false
+    }
+
+    /// Whether the executables built by `build` can run on the machine compiling them.
+    fn runs_on_host(&self) -> bool {
+        true
+    }
+
+    fn build(&self, _src: &str, _dest: &str) -> Result<(), CompilationError> {
+        Err(CompilationError::new(
+            "this target doesn't build any executable",
+        ))
+    }
+}
+
+/// Every available backend, the first one being the default.
+pub fn backends() -> Vec<Box<dyn Backend>> {
+    vec![
+        Box::new(rust::RustBackend::default()),
+        Box::new(c::CBackend),
+        Box::new(wasm::WasmBackend),
+        Box::new(asm::AsmBackend),
+        Box::new(elf::ElfBackend),
+        Box::new(llvm::LlvmBackend),
+        Box::new(js::JavaScriptBackend),
+        Box::new(js::TypeScriptBackend),
+        Box::new(python::PythonBackend),
+        Box::new(bf::BrainfuckBackend),
+    ]
+}
+
+pub fn backend(name: &str) -> Option<Box<dyn Backend>> {
+    backends()
+        .into_iter()
+        .find(|backend| backend.name() == name)
+}
+
+/// Statements generated for each instruction by the backends producing line-oriented source
+/// code. The nesting of loops is handled by `lower`, which indents their body.
+trait Lowering {
+    const INDENTATION: &'static str;
+
+    fn move_pointer_right(&mut self, n: usize) -> Vec<String>;
+    fn move_pointer_left(&mut self, n: usize) -> Vec<String>;
+    fn increment_cell(&mut self, n: u8) -> Vec<String>;
+    fn decrement_cell(&mut self, n: u8) -> Vec<String>;
+    fn display_cell(&mut self) -> Vec<String>;
+    fn replace_cell(&mut self) -> Vec<String>;
+    fn while_start(&mut self) -> Vec<String>;
+    fn while_end(&mut self) -> Vec<String>;
+
+    /// Statements generated in place of the body of a loop without any instruction.
+    fn empty_body(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+fn lower<L: Lowering>(lowering: &mut L, ast: &[parser::Instruction]) -> Vec<String> {
+    let mut lines = Vec::new();
+    do_lower(lowering, 0, ast, &mut lines);
+    lines
+}
+
+fn do_lower<L: Lowering>(
+    lowering: &mut L,
+    depth: usize,
+    ast: &[parser::Instruction],
+    lines: &mut Vec<String>,
+) {
+    for instruction in ast {
+        let statements = match instruction {
+            parser::Instruction::MovePointerRight(n) => lowering.move_pointer_right(*n),
+            parser::Instruction::MovePointerLeft(n) => lowering.move_pointer_left(*n),
+            parser::Instruction::IncrementCell(n) => lowering.increment_cell(*n),
+            parser::Instruction::DecrementCell(n) => lowering.decrement_cell(*n),
+            parser::Instruction::DisplayCell => lowering.display_cell(),
+            parser::Instruction::ReplaceCell => lowering.replace_cell(),
+            parser::Instruction::While(sub_ast) => {
+                push_indented::<L>(lines, depth, lowering.while_start());
+                if sub_ast.is_empty() {
+                    push_indented::<L>(lines, depth + 1, lowering.empty_body());
                 }
-                parser::Instruction::DecrementCell(n) => {
-                    program.body.push(format!("mem.decrement_cell({});", n))
+                do_lower(lowering, depth + 1, sub_ast, lines);
+                lowering.while_end()
+            }
+        };
+        push_indented::<L>(lines, depth, statements);
+    }
+}
+
+fn push_indented<L: Lowering>(lines: &mut Vec<String>, depth: usize, statements: Vec<String>) {
+    let indent = L::INDENTATION.repeat(depth);
+    lines.extend(statements.into_iter().map(|s| format!("{}{}", indent, s)));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{interpreter, parser};
+
+    use super::*;
+
+    fn toolchain_available(backend: &dyn Backend) -> bool {
+        backend
+            .toolchain()
+            .iter()
+            .all(|program| tool_available(program))
+    }
+
+    const SOURCE: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.,[.,]";
+    /// Input of `SOURCE`, echoing bytes from 128.
+    const INPUT: &str = "echo é\0";
+
+    fn interpret(encoding: Encoding) -> Vec<u8> {
+        let ast = parser::parse(SOURCE).unwrap();
+        let mut output = interpreter::io::Encoded(encoding, Vec::new());
+        interpreter::run(16, &ast, &mut INPUT.as_bytes(), &mut output).unwrap();
+
+        output.1
+    }
+
+    fn run_with_input(command: &mut std::process::Command) -> Vec<u8> {
+        let mut child = command
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        std::io::Write::write_all(child.stdin.as_mut().unwrap(), INPUT.as_bytes()).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+
+        output.stdout
+    }
+
+    #[test]
+    fn executables_behave_like_interpreter() {
+        let ast = parser::parse(SOURCE).unwrap();
+        let dir = std::env::temp_dir().join(format!("brainfuck-backends-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for encoding in Encoding::ALL {
+            let expected = interpret(encoding);
+            for backend in backends() {
+                if !backend.builds_executable() || !backend.runs_on_host() {
+                    continue;
                 }
-                parser::Instruction::DisplayCell => {
-                    program
-                        .body
-                        .push("print!(\"{}\", mem.current_cell_value() as char);".to_string());
+                if !toolchain_available(backend.as_ref()) {
+                    eprintln!("skipping backend {}: toolchain not found", backend.name());
+                    continue;
                 }
-                parser::Instruction::ReplaceCell => {
-                    program.read_buffer_definition = true;
-                    program.body.push(
-                        "reader.read_exact(&mut buffer).expect(\"failed to read from STDIN\");"
-                            .to_string(),
-                    );
-                    program
-                        .body
-                        .push("mem.set_current_cell_value(buffer[0]);".to_string());
+                let src = dir.join(format!("{}.{}", backend.name(), backend.extension()));
+                let dest = dir.join(backend.name());
+                backend
+                    .generate(
+                        16,
+                        encoding,
+                        &ast,
+                        &mut std::fs::File::create(&src).unwrap(),
+                    )
+                    .unwrap();
+                backend
+                    .build(src.to_str().unwrap(), dest.to_str().unwrap())
+                    .unwrap();
+
+                let output = run_with_input(&mut std::process::Command::new(&dest));
+                assert_eq!(
+                    expected,
+                    output,
+                    "backend {}, encoding {}",
+                    backend.name(),
+                    encoding
+                );
+            }
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn programs_fail_like_interpreter() {
+        let dir = TestDir::new("failures");
+        std::fs::write(
+            dir.join("runner.mjs"),
+            "import { run } from './program.mjs';
+            try {
+                process.stdout.write(run(new Uint8Array()));
+            } catch (error) {
+                process.stdout.write(error.output);
+                process.stderr.write(`Error: ${error.message}\\n`);
+                process.exitCode = 1;
+            }",
+        )
+        .unwrap();
+
+        for source in ["+.>+<<", "+.,"] {
+            let ast = parser::parse(source).unwrap();
+            let mut expected_output = Vec::new();
+            let err =
+                interpreter::run(16, &ast, &mut "".as_bytes(), &mut expected_output).unwrap_err();
+            let expected = (expected_output, format!("Error: {}\n", err));
+            for backend in backends() {
+                let command = match backend.name() {
+                    "python" => vec!["python3".to_string(), "program.py".to_string()],
+                    "js" => vec!["node".to_string(), "runner.mjs".to_string()],
+                    _ if backend.builds_executable() && backend.runs_on_host() => {
+                        vec![dir.join(backend.name()).to_str().unwrap().to_string()]
+                    }
+                    _ => continue,
+                };
+                let interpreted = !backend.builds_executable();
+                if !toolchain_available(backend.as_ref())
+                    || (interpreted && !tool_available(&command[0]))
+                {
+                    eprintln!("skipping backend {}: toolchain not found", backend.name());
+                    continue;
                 }
-                parser::Instruction::While(sub_ast) => {
-                    program
-                        .body
-                        .push("while mem.current_cell_value() != 0 {".to_string());
-                    do_generate(program, spacing + 2, sub_ast);
-                    program.body.push("}".to_string());
+                let src = if interpreted {
+                    dir.join(&format!("program.{}", backend.extension()))
+                } else {
+                    dir.join(&format!("{}.{}", backend.name(), backend.extension()))
+                };
+                backend
+                    .generate(
+                        16,
+                        Encoding::Raw,
+                        &ast,
+                        &mut std::fs::File::create(&src).unwrap(),
+                    )
+                    .unwrap();
+                if !interpreted {
+                    backend.build(src.to_str().unwrap(), &command[0]).unwrap();
                 }
+
+                let output = std::process::Command::new(&command[0])
+                    .args(&command[1..])
+                    .current_dir(&dir.0)
+                    .stdin(std::process::Stdio::null())
+                    .output()
+                    .unwrap();
+                assert_eq!(Some(1), output.status.code(), "backend {}", backend.name());
+                assert_eq!(
+                    expected,
+                    (output.stdout, String::from_utf8(output.stderr).unwrap()),
+                    "backend {}, program {}",
+                    backend.name(),
+                    source
+                );
             }
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::io::BufWriter;
+    #[test]
+    fn scripts_behave_like_interpreter() {
+        let ast = parser::parse(SOURCE).unwrap();
+        let dir = std::env::temp_dir().join(format!("brainfuck-scripts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("runner.mjs"),
+            "import { readFileSync } from 'fs';
+            import { run } from './program.mjs';
+            process.stdout.write(run(readFileSync(0)));",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("runner.cjs"),
+            "const fs = require('fs');
+            const input = fs.readFileSync(0);
+            const output = [];
+            let position = 0;
+            const env = {
+                read_byte: () => (position < input.length ? input[position++] : -1),
+                write_byte: (b) => output.push(b),
+            };
+            WebAssembly.instantiate(fs.readFileSync(process.argv[2]), { env }).then(({ instance }) => {
+                instance.exports.run();
+                process.stdout.write(Buffer.from(output));
+            });",
+        )
+        .unwrap();
 
-    use crate::parser;
+        for encoding in Encoding::ALL {
+            let expected = interpret(encoding);
+            for (name, file, command) in [
+                ("python", "program.py", vec!["python3", "program.py"]),
+                ("js", "program.mjs", vec!["node", "runner.mjs"]),
+                (
+                    "wasm",
+                    "program.wasm",
+                    vec!["node", "runner.cjs", "program.wasm"],
+                ),
+            ] {
+                if !tool_available(command[0]) {
+                    eprintln!("skipping backend {}: {} not found", name, command[0]);
+                    continue;
+                }
+                backend(name)
+                    .unwrap()
+                    .generate(
+                        16,
+                        encoding,
+                        &ast,
+                        &mut std::fs::File::create(dir.join(file)).unwrap(),
+                    )
+                    .unwrap();
 
-    use super::*;
+                let output = run_with_input(
+                    std::process::Command::new(command[0])
+                        .args(&command[1..])
+                        .current_dir(&dir),
+                );
+                assert_eq!(expected, output, "backend {}, encoding {}", name, encoding);
+            }
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 
     #[test]
-    fn generate_proper_source() {
-        let ast = vec![
-            parser::Instruction::IncrementCell(8),
-            parser::Instruction::While(vec![
-                parser::Instruction::MovePointerRight(1),
-                parser::Instruction::IncrementCell(4),
-            ]),
-            parser::Instruction::DisplayCell,
-        ];
-        let mut out = BufWriter::new(Vec::new());
-        rust::generate(4096, &ast, &mut out).unwrap();
-        let actual = String::from_utf8(out.into_inner().unwrap()).unwrap();
-        let expected = std::fs::read_to_string("golden-files/compiler/proper_source.txt").unwrap();
+    fn minified_program_behaves_like_interpreter() {
+        let source = std::fs::read_to_string("examples/tic-tac-toe.brainfuck").unwrap();
+        let ast = parser::parse(&source).unwrap();
+        let mut expected = Vec::new();
+        interpreter::run(512, &ast, &mut "5\n7\n6\n".as_bytes(), &mut expected).unwrap();
+
+        let mut minified = Vec::new();
+        backend("bf")
+            .unwrap()
+            .generate(512, Encoding::Raw, &ast, &mut minified)
+            .unwrap();
+        let ast = parser::parse(&String::from_utf8(minified).unwrap()).unwrap();
+        let mut actual = Vec::new();
+        interpreter::run(512, &ast, &mut "5\n7\n6\n".as_bytes(), &mut actual).unwrap();
 
         assert_eq!(expected, actual)
     }
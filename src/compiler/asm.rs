@@ -0,0 +1,243 @@
+use super::{run_toolchain, Backend, CompilationError};
+use crate::encoding::Encoding;
+use crate::parser;
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "program.s.txt")]
+struct ProgramTemplate<'a> {
+    program: &'a Program,
+}
+
+struct Program {
+    /// Instructions adding the default memory size to `%rdi`.
+    allocation: Vec<String>,
+    encoding: Encoding,
+    loops: usize,
+    body: Vec<String>,
+}
+
+impl Program {
+    pub fn new(default_memory: usize, encoding: Encoding) -> Self {
+        Program {
+            allocation: immediate("add", default_memory, "%rdi"),
+            encoding,
+            loops: 0,
+            body: Vec::new(),
+        }
+    }
+}
+
+/// Assembles the generated program with `as` and links it with `ld`.
+pub fn compile(src: &str, dest: &str) -> Result<(), CompilationError> {
+    let object = format!("{}.o", dest);
+    run_toolchain(
+        std::process::Command::new("as")
+            .arg("-o")
+            .arg(&object)
+            .arg(src),
+        "failed to assemble brainfuck generated program",
+    )?;
+
+    let linked = run_toolchain(
+        std::process::Command::new("ld")
+            .arg("-o")
+            .arg(dest)
+            .arg(&object),
+        "failed to link brainfuck generated program",
+    );
+    std::fs::remove_file(&object)
+        .map_err(|_| CompilationError::new("failed to remove the object file"))?;
+
+    linked
+}
+
+/// Generates a GNU assembler program for x86-64 Linux, performing its I/O through raw
+/// syscalls so it doesn't depend on any libc.
+pub fn generate<W: std::io::Write + ?Sized>(
+    default_memory: usize,
+    encoding: Encoding,
+    ast: &[parser::Instruction],
+    out: &mut W,
+) -> std::io::Result<()> {
+    let mut program = Program::new(default_memory, encoding);
+    do_generate(&mut program, ast);
+    write_program(&program, out)
+}
+
+fn write_program<W: std::io::Write + ?Sized>(
+    program: &Program,
+    out: &mut W,
+) -> std::io::Result<()> {
+    let tmpl = ProgramTemplate { program };
+
+    writeln!(out, "{}", tmpl.render().unwrap())
+}
+
+/// Applies `mnemonic` to `value` and `register`, the value being moved to `%rcx` first when it
+/// doesn't fit in the sign-extended 32-bit immediate of the instruction.
+fn immediate(mnemonic: &str, value: usize, register: &str) -> Vec<String> {
+    match i32::try_from(value) {
+        Ok(value) => vec![format!("{} ${}, {}", mnemonic, value, register)],
+        Err(_) => vec![
+            format!("movabs ${}, %rcx", value),
+            format!("{} %rcx, {}", mnemonic, register),
+        ],
+    }
+}
+
+fn do_generate(program: &mut Program, ast: &[parser::Instruction]) {
+    for instruction in ast {
+        match instruction {
+            parser::Instruction::MovePointerRight(n) => {
+                program.body.extend(immediate("add", *n, "%rbx"));
+                program.body.push("cmp %r13, %rbx".to_string());
+                program.body.push("jb 1f".to_string());
+                program.body.push("call grow_memory".to_string());
+                program.body.push("1:".to_string());
+            }
+            parser::Instruction::MovePointerLeft(n) => {
+                program.body.extend(immediate("sub", *n, "%rbx"));
+                program.body.push("jb fail_negative_address".to_string());
+                program.body.push("cmp %r12, %rbx".to_string());
+                program.body.push("jb fail_negative_address".to_string());
+            }
+            parser::Instruction::IncrementCell(n) => {
+                program.body.push(format!("addb ${}, (%rbx)", n))
+            }
+            parser::Instruction::DecrementCell(n) => {
+                program.body.push(format!("subb ${}, (%rbx)", n))
+            }
+            parser::Instruction::DisplayCell => program.body.push("call write_cell".to_string()),
+            parser::Instruction::ReplaceCell => program.body.push("call read_cell".to_string()),
+            parser::Instruction::While(sub_ast) => {
+                let id = program.loops;
+                program.loops += 1;
+                program.body.push(format!("loop_{}_start:", id));
+                program.body.push("cmpb $0, (%rbx)".to_string());
+                program.body.push(format!("je loop_{}_end", id));
+                do_generate(program, sub_ast);
+                program.body.push(format!("jmp loop_{}_start", id));
+                program.body.push(format!("loop_{}_end:", id));
+            }
+        }
+    }
+}
+
+pub struct AsmBackend;
+
+impl Backend for AsmBackend {
+    fn name(&self) -> &'static str {
+        "asm"
+    }
+
+    fn description(&self) -> &'static str {
+        "x86-64 Linux assembly assembled with as and linked with ld"
+    }
+
+    fn extension(&self) -> &'static str {
+        "s"
+    }
+
+    fn toolchain(&self) -> &'static [&'static str] {
+        &["as", "ld"]
+    }
+
+    fn generate(
+        &self,
+        default_memory: usize,
+        encoding: Encoding,
+        ast: &[parser::Instruction],
+        out: &mut dyn std::io::Write,
+    ) -> std::io::Result<()> {
+        generate(default_memory, encoding, ast, out)
+    }
+
+    fn builds_executable(&self) -> bool {
+        true
+    }
+
+    fn runs_on_host(&self) -> bool {
+        cfg!(all(target_os = "linux", target_arch = "x86_64"))
+    }
+
+    fn build(&self, src: &str, dest: &str) -> Result<(), CompilationError> {
+        compile(src, dest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufWriter;
+
+    use crate::parser;
+
+    use super::*;
+
+    #[test]
+    fn generate_proper_source() {
+        let ast = vec![
+            parser::Instruction::IncrementCell(8),
+            parser::Instruction::While(vec![
+                parser::Instruction::MovePointerRight(1),
+                parser::Instruction::IncrementCell(4),
+            ]),
+            parser::Instruction::DisplayCell,
+        ];
+        let mut out = BufWriter::new(Vec::new());
+        generate(4096, Encoding::Raw, &ast, &mut out).unwrap();
+        let actual = String::from_utf8(out.into_inner().unwrap()).unwrap();
+        let expected =
+            std::fs::read_to_string("golden-files/compiler/proper_source.s.txt").unwrap();
+
+        assert_eq!(expected, actual)
+    }
+
+    // GNU as and the Linux syscalls of the program are only available on x86-64 Linux.
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[ignore = "requires as and ld"]
+    fn compile_hello_world() {
+        assert_eq!(
+            "Hello World!\n",
+            crate::compiler::build_hello_world(&AsmBackend)
+        );
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[ignore = "requires as and ld"]
+    fn move_beyond_32_bits() {
+        let ast = [
+            parser::Instruction::MovePointerRight(5),
+            parser::Instruction::MovePointerLeft((1 << 32) + 1),
+        ];
+        let dir = crate::compiler::TestDir::new("asm-far");
+        let src = dir.join("far.s");
+        let program = dir.join("far");
+        generate(
+            16,
+            Encoding::Raw,
+            &ast,
+            &mut std::fs::File::create(&src).unwrap(),
+        )
+        .unwrap();
+        compile(src.to_str().unwrap(), program.to_str().unwrap()).unwrap();
+
+        let output = std::process::Command::new(&program).output().unwrap();
+        assert_eq!(Some(1), output.status.code());
+        assert_eq!(
+            "Error: failed during execution: negative memory address are invalid\n",
+            String::from_utf8(output.stderr).unwrap()
+        );
+    }
+
+    #[test]
+    fn generate_memory_beyond_32_bits() {
+        let mut out = Vec::new();
+        generate(3_000_000_000, Encoding::Raw, &[], &mut out).unwrap();
+        let source = String::from_utf8(out).unwrap();
+
+        assert!(source.contains("    movabs $3000000000, %rcx\n    add %rcx, %rdi\n"));
+    }
+}
@@ -0,0 +1,151 @@
+use super::Backend;
+use crate::encoding::Encoding;
+use crate::{optimizer, parser};
+
+/// Serialises the AST back to brainfuck, on a single line and without any comment.
+pub fn generate<W: std::io::Write + ?Sized>(
+    ast: &[parser::Instruction],
+    out: &mut W,
+) -> std::io::Result<()> {
+    let mut source = String::new();
+    do_generate(&mut source, ast);
+
+    writeln!(out, "{}", source)
+}
+
+fn do_generate(source: &mut String, ast: &[parser::Instruction]) {
+    for instruction in ast {
+        match instruction {
+            parser::Instruction::MovePointerRight(n) => source.push_str(&">".repeat(*n)),
+            parser::Instruction::MovePointerLeft(n) => source.push_str(&"<".repeat(*n)),
+            parser::Instruction::IncrementCell(n) => source.push_str(&"+".repeat(*n as usize)),
+            parser::Instruction::DecrementCell(n) => source.push_str(&"-".repeat(*n as usize)),
+            parser::Instruction::DisplayCell => source.push('.'),
+            parser::Instruction::ReplaceCell => source.push(','),
+            parser::Instruction::While(sub_ast) => {
+                source.push('[');
+                do_generate(source, sub_ast);
+                source.push(']');
+            }
+        }
+    }
+}
+
+/// Pretty-prints the AST as brainfuck, brackets being on their own lines and loop bodies indented
+/// with four spaces. Comments are not preserved.
+pub fn format<W: std::io::Write + ?Sized>(
+    ast: &[parser::Instruction],
+    out: &mut W,
+) -> std::io::Result<()> {
+    do_format(ast, 0, out)
+}
+
+fn do_format<W: std::io::Write + ?Sized>(
+    ast: &[parser::Instruction],
+    depth: usize,
+    out: &mut W,
+) -> std::io::Result<()> {
+    let indentation = "    ".repeat(depth);
+    let mut line = String::new();
+    for instruction in ast {
+        match instruction {
+            parser::Instruction::While(sub_ast) => {
+                if !line.is_empty() {
+                    writeln!(out, "{}{}", indentation, line)?;
+                    line.clear();
+                }
+                writeln!(out, "{}[", indentation)?;
+                do_format(sub_ast, depth + 1, out)?;
+                writeln!(out, "{}]", indentation)?;
+            }
+            instruction => do_generate(&mut line, std::slice::from_ref(instruction)),
+        }
+    }
+    if !line.is_empty() {
+        writeln!(out, "{}{}", indentation, line)?;
+    }
+
+    Ok(())
+}
+
+/// Minifies the program: comments are stripped, `+-` and `<>` pairs are cancelled and dead
+/// loops are removed.
+pub struct BrainfuckBackend;
+
+impl Backend for BrainfuckBackend {
+    fn name(&self) -> &'static str {
+        "bf"
+    }
+
+    fn description(&self) -> &'static str {
+        "minified brainfuck program"
+    }
+
+    fn extension(&self) -> &'static str {
+        "min.brainfuck"
+    }
+
+    fn generate(
+        &self,
+        _default_memory: usize,
+        encoding: Encoding,
+        ast: &[parser::Instruction],
+        out: &mut dyn std::io::Write,
+    ) -> std::io::Result<()> {
+        // The output of a brainfuck program can't be encoded by another brainfuck program.
+        if encoding != Encoding::Raw {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("the bf target doesn't support the {} encoding", encoding),
+            ));
+        }
+        generate(
+            &optimizer::remove_dead_loops(&optimizer::cancel_opposites(ast)),
+            out,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufWriter;
+
+    use super::*;
+
+    #[test]
+    fn minified_source() {
+        let source = "[comment loop, ignored.] ++ +- [ >+<- ] > <<> . set up, display";
+        let ast = optimizer::remove_dead_loops(&optimizer::cancel_opposites(
+            &parser::parse(source).unwrap(),
+        ));
+        let mut out = BufWriter::new(Vec::new());
+        generate(&ast, &mut out).unwrap();
+        let actual = String::from_utf8(out.into_inner().unwrap()).unwrap();
+
+        assert_eq!("++[>+<-].,\n", actual)
+    }
+
+    #[test]
+    fn formatted_source() {
+        let ast = parser::parse("++ [>+ [-] <-] comment .").unwrap();
+        let mut out = Vec::new();
+        format(&ast, &mut out).unwrap();
+
+        assert_eq!(
+            "++\n[\n    >+\n    [\n        -\n    ]\n    <-\n]\n.\n",
+            String::from_utf8(out).unwrap()
+        )
+    }
+
+    #[test]
+    fn reject_encoded_output() {
+        let ast = parser::parse("+.").unwrap();
+        let mut out = Vec::new();
+        let err = BrainfuckBackend
+            .generate(4096, Encoding::Latin1, &ast, &mut out)
+            .unwrap_err();
+
+        assert_eq!(std::io::ErrorKind::Unsupported, err.kind());
+        assert!(out.is_empty());
+    }
+}
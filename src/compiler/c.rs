@@ -0,0 +1,172 @@
+use super::{lower, run_toolchain, Backend, CompilationError, Lowering};
+use crate::encoding::Encoding;
+use crate::parser;
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "program.c.txt")]
+struct ProgramTemplate<'a> {
+    program: &'a Program,
+}
+
+struct Program {
+    default_memory: usize,
+    encoding: Encoding,
+    body: Vec<String>,
+}
+
+impl Program {
+    pub fn new(default_memory: usize, encoding: Encoding) -> Self {
+        Program {
+            default_memory,
+            encoding,
+            body: Vec::new(),
+        }
+    }
+}
+
+/// Compiles the generated C program with the compiler set in the `CC` environment variable,
+/// falling back to `cc`.
+pub fn compile(src: &str, dest: &str) -> Result<(), CompilationError> {
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    run_toolchain(
+        std::process::Command::new(cc)
+            .arg("-std=c99")
+            .arg("-O2")
+            .arg("-o")
+            .arg(dest)
+            .arg(src),
+        "failed to compile brainfuck C generated program",
+    )
+}
+
+pub fn generate<W: std::io::Write + ?Sized>(
+    default_memory: usize,
+    encoding: Encoding,
+    ast: &[parser::Instruction],
+    out: &mut W,
+) -> std::io::Result<()> {
+    let mut program = Program::new(default_memory, encoding);
+    program.body = lower(&mut program, ast);
+    write_program(&program, out)
+}
+
+fn write_program<W: std::io::Write + ?Sized>(
+    program: &Program,
+    out: &mut W,
+) -> std::io::Result<()> {
+    let tmpl = ProgramTemplate { program };
+
+    writeln!(out, "{}", tmpl.render().unwrap())
+}
+
+impl Lowering for Program {
+    const INDENTATION: &'static str = "    ";
+
+    fn move_pointer_right(&mut self, n: usize) -> Vec<String> {
+        vec![format!("move_right(&mem, {});", n)]
+    }
+
+    fn move_pointer_left(&mut self, n: usize) -> Vec<String> {
+        vec![format!("move_left(&mem, {});", n)]
+    }
+
+    fn increment_cell(&mut self, n: u8) -> Vec<String> {
+        vec![format!("mem.cells[mem.index] += {};", n)]
+    }
+
+    fn decrement_cell(&mut self, n: u8) -> Vec<String> {
+        vec![format!("mem.cells[mem.index] -= {};", n)]
+    }
+
+    fn display_cell(&mut self) -> Vec<String> {
+        vec!["write_cell(mem.cells[mem.index]);".to_string()]
+    }
+
+    fn replace_cell(&mut self) -> Vec<String> {
+        vec!["read_cell(&mem);".to_string()]
+    }
+
+    fn while_start(&mut self) -> Vec<String> {
+        vec!["while (mem.cells[mem.index] != 0) {".to_string()]
+    }
+
+    fn while_end(&mut self) -> Vec<String> {
+        vec!["}".to_string()]
+    }
+}
+
+pub struct CBackend;
+
+impl Backend for CBackend {
+    fn name(&self) -> &'static str {
+        "c"
+    }
+
+    fn description(&self) -> &'static str {
+        "C99 program compiled with cc"
+    }
+
+    fn extension(&self) -> &'static str {
+        "c"
+    }
+
+    fn toolchain(&self) -> &'static [&'static str] {
+        &["cc"]
+    }
+
+    fn generate(
+        &self,
+        default_memory: usize,
+        encoding: Encoding,
+        ast: &[parser::Instruction],
+        out: &mut dyn std::io::Write,
+    ) -> std::io::Result<()> {
+        generate(default_memory, encoding, ast, out)
+    }
+
+    fn builds_executable(&self) -> bool {
+        true
+    }
+
+    fn build(&self, src: &str, dest: &str) -> Result<(), CompilationError> {
+        compile(src, dest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufWriter;
+
+    use crate::parser;
+
+    use super::*;
+
+    #[test]
+    fn generate_proper_source() {
+        let ast = vec![
+            parser::Instruction::IncrementCell(8),
+            parser::Instruction::While(vec![
+                parser::Instruction::MovePointerRight(1),
+                parser::Instruction::IncrementCell(4),
+            ]),
+            parser::Instruction::DisplayCell,
+        ];
+        let mut out = BufWriter::new(Vec::new());
+        generate(4096, Encoding::Raw, &ast, &mut out).unwrap();
+        let actual = String::from_utf8(out.into_inner().unwrap()).unwrap();
+        let expected =
+            std::fs::read_to_string("golden-files/compiler/proper_source.c.txt").unwrap();
+
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    #[ignore = "requires a C compiler"]
+    fn compile_hello_world() {
+        assert_eq!(
+            "Hello World!\n",
+            crate::compiler::build_hello_world(&CBackend)
+        );
+    }
+}
@@ -0,0 +1,466 @@
+use super::{Backend, CompilationError};
+use crate::encoding::Encoding;
+use crate::parser;
+
+const BASE_ADDRESS: u64 = 0x400000;
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const CODE_OFFSET: usize = ELF_HEADER_SIZE + PROGRAM_HEADER_SIZE;
+
+const ALLOCATION_ERROR: &str = "Error: failed during execution: cannot allocate memory\n";
+const NEGATIVE_ADDRESS_ERROR: &str =
+    "Error: failed during execution: negative memory address are invalid\n";
+const READ_ERROR: &str = "Error: failed during execution: cannot read STDIN\n";
+
+#[derive(Clone, Copy)]
+struct Label(usize);
+
+/// Machine code being written, with the positions of the `rel32` operands waiting for their
+/// label to be placed.
+struct Assembler {
+    code: Vec<u8>,
+    labels: Vec<Option<usize>>,
+    fixups: Vec<(usize, Label)>,
+}
+
+impl Assembler {
+    fn new() -> Self {
+        Assembler {
+            code: Vec::new(),
+            labels: Vec::new(),
+            fixups: Vec::new(),
+        }
+    }
+
+    fn label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    fn place(&mut self, label: Label) {
+        self.labels[label.0] = Some(self.code.len());
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend(bytes);
+    }
+
+    fn emit_u32(&mut self, value: u32) {
+        self.code.extend(value.to_le_bytes());
+    }
+
+    /// Emits `opcode` followed by `value` as a sign-extended 32-bit immediate when it fits,
+    /// or moves `value` to `%rcx` and emits `register`, the same instruction taking `%rcx` as
+    /// its operand, otherwise.
+    fn emit_imm(&mut self, opcode: &[u8], register: &[u8], value: usize) {
+        match i32::try_from(value) {
+            Ok(value) => {
+                self.emit(opcode);
+                self.emit_u32(value as u32);
+            }
+            Err(_) => {
+                // movabs $value, %rcx
+                self.emit(&[0x48, 0xb9]);
+                self.emit(&(value as u64).to_le_bytes());
+                self.emit(register);
+            }
+        }
+    }
+
+    fn emit_rel32(&mut self, opcode: &[u8], label: Label) {
+        self.emit(opcode);
+        self.fixups.push((self.code.len(), label));
+        self.emit_u32(0);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        for (position, label) in &self.fixups {
+            let target = self.labels[label.0].expect("label was never placed");
+            let offset = target as i64 - (*position as i64 + 4);
+            self.code[*position..*position + 4].copy_from_slice(&(offset as i32).to_le_bytes());
+        }
+
+        self.code
+    }
+}
+
+struct Routines {
+    grow_memory: Label,
+    resize_memory: Label,
+    write_cell: Label,
+    read_cell: Label,
+    fail_allocation: Label,
+    fail_negative_address: Label,
+    fail_read: Label,
+}
+
+/// Generates a static x86-64 Linux ELF executable. It follows the same conventions as the
+/// `asm` backend: `%rbx` points to the current cell, `%r12` to the first one, `%r13` right
+/// after the last allocated one, and the memory grows through the `brk` syscall.
+pub fn generate<W: std::io::Write + ?Sized>(
+    default_memory: usize,
+    encoding: Encoding,
+    ast: &[parser::Instruction],
+    out: &mut W,
+) -> std::io::Result<()> {
+    let mut asm = Assembler::new();
+    let routines = Routines {
+        grow_memory: asm.label(),
+        resize_memory: asm.label(),
+        write_cell: asm.label(),
+        read_cell: asm.label(),
+        fail_allocation: asm.label(),
+        fail_negative_address: asm.label(),
+        fail_read: asm.label(),
+    };
+
+    // mov $12, %eax; xor %edi, %edi; syscall
+    asm.emit(&[0xb8, 12, 0, 0, 0, 0x31, 0xff, 0x0f, 0x05]);
+    // mov %rax, %r12; mov %rax, %rbx
+    asm.emit(&[0x49, 0x89, 0xc4, 0x48, 0x89, 0xc3]);
+    // mov %rax, %rdi; add $default_memory, %rdi
+    asm.emit(&[0x48, 0x89, 0xc7]);
+    asm.emit_imm(&[0x48, 0x81, 0xc7], &[0x48, 0x01, 0xcf], default_memory);
+    asm.emit_rel32(&[0xe8], routines.resize_memory);
+
+    do_generate(&mut asm, &routines, ast);
+
+    // mov $60, %eax; xor %edi, %edi; syscall
+    asm.emit(&[0xb8, 60, 0, 0, 0, 0x31, 0xff, 0x0f, 0x05]);
+
+    write_routines(&mut asm, &routines, encoding);
+
+    let code = asm.finish();
+    let size = (CODE_OFFSET + code.len()) as u64;
+
+    let mut header = Vec::with_capacity(CODE_OFFSET);
+    header.extend([0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    header.extend(2u16.to_le_bytes());
+    header.extend(0x3eu16.to_le_bytes());
+    header.extend(1u32.to_le_bytes());
+    header.extend((BASE_ADDRESS + CODE_OFFSET as u64).to_le_bytes());
+    header.extend((ELF_HEADER_SIZE as u64).to_le_bytes());
+    header.extend(0u64.to_le_bytes());
+    header.extend(0u32.to_le_bytes());
+    header.extend((ELF_HEADER_SIZE as u16).to_le_bytes());
+    header.extend((PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+    header.extend(1u16.to_le_bytes());
+    header.extend(64u16.to_le_bytes());
+    header.extend(0u16.to_le_bytes());
+    header.extend(0u16.to_le_bytes());
+
+    header.extend(1u32.to_le_bytes());
+    header.extend(5u32.to_le_bytes());
+    header.extend(0u64.to_le_bytes());
+    header.extend(BASE_ADDRESS.to_le_bytes());
+    header.extend(BASE_ADDRESS.to_le_bytes());
+    header.extend(size.to_le_bytes());
+    header.extend(size.to_le_bytes());
+    header.extend(0x1000u64.to_le_bytes());
+
+    out.write_all(&header)?;
+    out.write_all(&code)
+}
+
+/// The generated file already is an executable, compiling only copies it to its
+/// destination and makes it executable.
+pub fn compile(src: &str, dest: &str) -> Result<(), CompilationError> {
+    std::fs::copy(src, dest).map_err(|_| CompilationError::new("failed to copy the executable"))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        std::fs::set_permissions(dest, std::fs::Permissions::from_mode(0o755))
+            .map_err(|_| CompilationError::new("failed to make the program executable"))?;
+    }
+
+    Ok(())
+}
+
+fn do_generate(asm: &mut Assembler, routines: &Routines, ast: &[parser::Instruction]) {
+    for instruction in ast {
+        match instruction {
+            parser::Instruction::MovePointerRight(n) => {
+                let done = asm.label();
+                // add $n, %rbx; cmp %r13, %rbx; jb done; call grow_memory
+                asm.emit_imm(&[0x48, 0x81, 0xc3], &[0x48, 0x01, 0xcb], *n);
+                asm.emit(&[0x4c, 0x39, 0xeb]);
+                asm.emit_rel32(&[0x0f, 0x82], done);
+                asm.emit_rel32(&[0xe8], routines.grow_memory);
+                asm.place(done);
+            }
+            parser::Instruction::MovePointerLeft(n) => {
+                // sub $n, %rbx; jb fail_negative_address; cmp %r12, %rbx;
+                // jb fail_negative_address
+                asm.emit_imm(&[0x48, 0x81, 0xeb], &[0x48, 0x29, 0xcb], *n);
+                asm.emit_rel32(&[0x0f, 0x82], routines.fail_negative_address);
+                asm.emit(&[0x4c, 0x39, 0xe3]);
+                asm.emit_rel32(&[0x0f, 0x82], routines.fail_negative_address);
+            }
+            // addb $n, (%rbx)
+            parser::Instruction::IncrementCell(n) => asm.emit(&[0x80, 0x03, *n]),
+            // subb $n, (%rbx)
+            parser::Instruction::DecrementCell(n) => asm.emit(&[0x80, 0x2b, *n]),
+            parser::Instruction::DisplayCell => asm.emit_rel32(&[0xe8], routines.write_cell),
+            parser::Instruction::ReplaceCell => asm.emit_rel32(&[0xe8], routines.read_cell),
+            parser::Instruction::While(sub_ast) => {
+                let start = asm.label();
+                let end = asm.label();
+                // cmpb $0, (%rbx); je end
+                asm.place(start);
+                asm.emit(&[0x80, 0x3b, 0x00]);
+                asm.emit_rel32(&[0x0f, 0x84], end);
+                do_generate(asm, routines, sub_ast);
+                asm.emit_rel32(&[0xe9], start);
+                asm.place(end);
+            }
+        }
+    }
+}
+
+fn write_routines(asm: &mut Assembler, routines: &Routines, encoding: Encoding) {
+    let allocation_error = asm.label();
+    let negative_address_error = asm.label();
+    let read_error = asm.label();
+    let fail = asm.label();
+
+    // lea 1(%rbx), %rdi
+    asm.place(routines.grow_memory);
+    asm.emit(&[0x48, 0x8d, 0x7b, 0x01]);
+    // mov %rdi, %r14; mov $12, %eax; syscall; cmp %r14, %rax; jb fail_allocation
+    asm.place(routines.resize_memory);
+    asm.emit(&[
+        0x49, 0x89, 0xfe, 0xb8, 12, 0, 0, 0, 0x0f, 0x05, 0x4c, 0x39, 0xf0,
+    ]);
+    asm.emit_rel32(&[0x0f, 0x82], routines.fail_allocation);
+    // mov %rax, %r13; ret
+    asm.emit(&[0x49, 0x89, 0xc5, 0xc3]);
+
+    asm.place(routines.write_cell);
+    write_cell(asm, encoding);
+
+    // xor %eax, %eax; xor %edi, %edi; mov %rbx, %rsi; mov $1, %edx; syscall
+    asm.place(routines.read_cell);
+    asm.emit(&[0x31, 0xc0, 0x31, 0xff, 0x48, 0x89, 0xde]);
+    asm.emit(&[0xba, 1, 0, 0, 0, 0x0f, 0x05]);
+    // cmp $1, %rax; jne fail_read; ret
+    asm.emit(&[0x48, 0x83, 0xf8, 0x01]);
+    asm.emit_rel32(&[0x0f, 0x85], routines.fail_read);
+    asm.emit(&[0xc3]);
+
+    for (routine, message, text) in [
+        (routines.fail_allocation, allocation_error, ALLOCATION_ERROR),
+        (
+            routines.fail_negative_address,
+            negative_address_error,
+            NEGATIVE_ADDRESS_ERROR,
+        ),
+        (routines.fail_read, read_error, READ_ERROR),
+    ] {
+        // lea message(%rip), %rsi; mov $len, %edx; jmp fail
+        asm.place(routine);
+        asm.emit_rel32(&[0x48, 0x8d, 0x35], message);
+        asm.emit(&[0xba]);
+        asm.emit_u32(text.len() as u32);
+        asm.emit_rel32(&[0xe9], fail);
+    }
+
+    // mov $1, %eax; mov $2, %edi; syscall; mov $60, %eax; mov $1, %edi; syscall
+    asm.place(fail);
+    asm.emit(&[0xb8, 1, 0, 0, 0, 0xbf, 2, 0, 0, 0, 0x0f, 0x05]);
+    asm.emit(&[0xb8, 60, 0, 0, 0, 0xbf, 1, 0, 0, 0, 0x0f, 0x05]);
+
+    for (label, text) in [
+        (allocation_error, ALLOCATION_ERROR),
+        (negative_address_error, NEGATIVE_ADDRESS_ERROR),
+        (read_error, READ_ERROR),
+    ] {
+        asm.place(label);
+        asm.emit(text.as_bytes());
+    }
+}
+
+/// Writes the current cell the same way as the `write_cell` routine of the `asm` backend.
+fn write_cell(asm: &mut Assembler, encoding: Encoding) {
+    match encoding {
+        Encoding::Raw => {
+            // mov $1, %eax; mov $1, %edi; mov %rbx, %rsi; mov $1, %edx; syscall; ret
+            asm.emit(&[0xb8, 1, 0, 0, 0, 0xbf, 1, 0, 0, 0, 0x48, 0x89, 0xde]);
+            asm.emit(&[0xba, 1, 0, 0, 0, 0x0f, 0x05, 0xc3]);
+        }
+        Encoding::Latin1 => {
+            let two_bytes = asm.label();
+            // movzbl (%rbx), %eax; cmp $0x80, %eax; jae two_bytes
+            asm.emit(&[0x0f, 0xb6, 0x03, 0x3d, 0x80, 0, 0, 0]);
+            asm.emit_rel32(&[0x0f, 0x83], two_bytes);
+            // mov $1, %eax; mov $1, %edi; mov %rbx, %rsi; mov $1, %edx; syscall; ret
+            asm.emit(&[0xb8, 1, 0, 0, 0, 0xbf, 1, 0, 0, 0, 0x48, 0x89, 0xde]);
+            asm.emit(&[0xba, 1, 0, 0, 0, 0x0f, 0x05, 0xc3]);
+            // mov %eax, %ecx; shr $6, %ecx; or $0xc0, %ecx; and $0x3f, %eax; or $0x80, %eax
+            asm.place(two_bytes);
+            asm.emit(&[0x89, 0xc1, 0xc1, 0xe9, 0x06, 0x81, 0xc9, 0xc0, 0, 0, 0]);
+            asm.emit(&[0x83, 0xe0, 0x3f, 0x0d, 0x80, 0, 0, 0]);
+            // sub $8, %rsp; mov %cl, (%rsp); mov %al, 1(%rsp)
+            asm.emit(&[
+                0x48, 0x83, 0xec, 0x08, 0x88, 0x0c, 0x24, 0x88, 0x44, 0x24, 0x01,
+            ]);
+            // mov $1, %eax; mov $1, %edi; mov %rsp, %rsi; mov $2, %edx; syscall
+            asm.emit(&[0xb8, 1, 0, 0, 0, 0xbf, 1, 0, 0, 0, 0x48, 0x89, 0xe6]);
+            asm.emit(&[0xba, 2, 0, 0, 0, 0x0f, 0x05]);
+            // add $8, %rsp; ret
+            asm.emit(&[0x48, 0x83, 0xc4, 0x08, 0xc3]);
+        }
+        Encoding::Decimal => {
+            let digit = asm.label();
+            // movzbl (%rbx), %eax; sub $8, %rsp; lea 7(%rsp), %rsi; movb $10, (%rsi)
+            asm.emit(&[0x0f, 0xb6, 0x03, 0x48, 0x83, 0xec, 0x08]);
+            asm.emit(&[0x48, 0x8d, 0x74, 0x24, 0x07, 0xc6, 0x06, 0x0a]);
+            // mov $10, %ecx
+            asm.emit(&[0xb9, 10, 0, 0, 0]);
+            // xor %edx, %edx; div %ecx; add $48, %dl; dec %rsi; mov %dl, (%rsi)
+            asm.place(digit);
+            asm.emit(&[
+                0x31, 0xd2, 0xf7, 0xf1, 0x80, 0xc2, 0x30, 0x48, 0xff, 0xce, 0x88, 0x16,
+            ]);
+            // test %eax, %eax; jnz digit
+            asm.emit(&[0x85, 0xc0]);
+            asm.emit_rel32(&[0x0f, 0x85], digit);
+            // lea 8(%rsp), %rdx; sub %rsi, %rdx; mov $1, %eax; mov $1, %edi; syscall
+            asm.emit(&[0x48, 0x8d, 0x54, 0x24, 0x08, 0x48, 0x29, 0xf2]);
+            asm.emit(&[0xb8, 1, 0, 0, 0, 0xbf, 1, 0, 0, 0, 0x0f, 0x05]);
+            // add $8, %rsp; ret
+            asm.emit(&[0x48, 0x83, 0xc4, 0x08, 0xc3]);
+        }
+    }
+}
+
+pub struct ElfBackend;
+
+impl Backend for ElfBackend {
+    fn name(&self) -> &'static str {
+        "elf"
+    }
+
+    fn description(&self) -> &'static str {
+        "static x86-64 Linux executable, without any toolchain"
+    }
+
+    fn extension(&self) -> &'static str {
+        "elf"
+    }
+
+    fn generate(
+        &self,
+        default_memory: usize,
+        encoding: Encoding,
+        ast: &[parser::Instruction],
+        out: &mut dyn std::io::Write,
+    ) -> std::io::Result<()> {
+        generate(default_memory, encoding, ast, out)
+    }
+
+    fn builds_executable(&self) -> bool {
+        true
+    }
+
+    fn runs_on_host(&self) -> bool {
+        cfg!(all(target_os = "linux", target_arch = "x86_64"))
+    }
+
+    fn build(&self, src: &str, dest: &str) -> Result<(), CompilationError> {
+        compile(src, dest)
+    }
+}
+
+// The tests run the generated executables, which only work on x86-64 Linux.
+#[cfg(all(test, target_os = "linux", target_arch = "x86_64"))]
+mod tests {
+    use crate::parser;
+
+    use super::*;
+
+    fn build(name: &str, source: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+        let ast = parser::parse(source).unwrap();
+        let dir =
+            std::env::temp_dir().join(format!("brainfuck-elf-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let src = dir.join(format!("{}.elf", name));
+        let dest = dir.join(name);
+        generate(
+            4,
+            Encoding::Raw,
+            &ast,
+            &mut std::fs::File::create(&src).unwrap(),
+        )
+        .unwrap();
+        compile(src.to_str().unwrap(), dest.to_str().unwrap()).unwrap();
+
+        (dir, dest)
+    }
+
+    #[test]
+    fn run_hello_world() {
+        let source = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+        let (dir, program) = build("hello_world", source);
+
+        let output = std::process::Command::new(&program).output().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!("Hello World!\n", String::from_utf8(output.stdout).unwrap());
+    }
+
+    #[test]
+    fn run_echo() {
+        let (dir, program) = build("echo", ",.,.");
+
+        let mut child = std::process::Command::new(&program)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        std::io::Write::write_all(child.stdin.as_mut().unwrap(), b"ok").unwrap();
+        let output = child.wait_with_output().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!("ok", String::from_utf8(output.stdout).unwrap());
+    }
+
+    #[test]
+    fn move_beyond_32_bits() {
+        let ast = [
+            parser::Instruction::MovePointerRight(5),
+            parser::Instruction::MovePointerLeft((1 << 32) + 1),
+        ];
+        let dir = std::env::temp_dir().join(format!("brainfuck-elf-far-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("far.elf");
+        let program = dir.join("far");
+        generate(
+            16,
+            Encoding::Raw,
+            &ast,
+            &mut std::fs::File::create(&src).unwrap(),
+        )
+        .unwrap();
+        compile(src.to_str().unwrap(), program.to_str().unwrap()).unwrap();
+
+        let output = std::process::Command::new(&program).output().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            NEGATIVE_ADDRESS_ERROR,
+            String::from_utf8(output.stderr).unwrap()
+        );
+    }
+
+    #[test]
+    fn negative_memory_address() {
+        let (dir, program) = build("negative", "+<");
+
+        let output = std::process::Command::new(&program).output().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(Some(1), output.status.code());
+        assert_eq!(
+            NEGATIVE_ADDRESS_ERROR,
+            String::from_utf8(output.stderr).unwrap()
+        );
+    }
+}
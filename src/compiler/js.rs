@@ -0,0 +1,216 @@
+use super::{lower, Backend, Lowering};
+use crate::encoding::Encoding;
+use crate::parser;
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "program.js.txt")]
+struct ProgramTemplate<'a> {
+    program: &'a Program,
+}
+
+struct Program {
+    default_memory: usize,
+    encoding: Encoding,
+    typescript: bool,
+    body: Vec<String>,
+}
+
+impl Program {
+    pub fn new(default_memory: usize, encoding: Encoding, typescript: bool) -> Self {
+        Program {
+            default_memory,
+            encoding,
+            typescript,
+            body: Vec::new(),
+        }
+    }
+}
+
+/// Generates a self-contained JavaScript module exporting a
+/// `run(input: Uint8Array): Uint8Array` function which returns everything the program
+/// displayed.
+pub fn generate_javascript<W: std::io::Write + ?Sized>(
+    default_memory: usize,
+    encoding: Encoding,
+    ast: &[parser::Instruction],
+    out: &mut W,
+) -> std::io::Result<()> {
+    let mut program = Program::new(default_memory, encoding, false);
+    program.body = lower(&mut program, ast);
+    write_program(&program, out)
+}
+
+/// Generates the same module as `generate_javascript` with TypeScript type annotations.
+pub fn generate_typescript<W: std::io::Write + ?Sized>(
+    default_memory: usize,
+    encoding: Encoding,
+    ast: &[parser::Instruction],
+    out: &mut W,
+) -> std::io::Result<()> {
+    let mut program = Program::new(default_memory, encoding, true);
+    program.body = lower(&mut program, ast);
+    write_program(&program, out)
+}
+
+fn write_program<W: std::io::Write + ?Sized>(
+    program: &Program,
+    out: &mut W,
+) -> std::io::Result<()> {
+    let tmpl = ProgramTemplate { program };
+
+    writeln!(out, "{}", tmpl.render().unwrap())
+}
+
+impl Lowering for Program {
+    const INDENTATION: &'static str = "  ";
+
+    fn move_pointer_right(&mut self, n: usize) -> Vec<String> {
+        vec![format!("moveRight({});", n)]
+    }
+
+    fn move_pointer_left(&mut self, n: usize) -> Vec<String> {
+        vec![format!("moveLeft({});", n)]
+    }
+
+    fn increment_cell(&mut self, n: u8) -> Vec<String> {
+        vec![format!("cells[index] += {};", n)]
+    }
+
+    fn decrement_cell(&mut self, n: u8) -> Vec<String> {
+        vec![format!("cells[index] -= {};", n)]
+    }
+
+    fn display_cell(&mut self) -> Vec<String> {
+        vec!["writeCell(cells[index]);".to_string()]
+    }
+
+    fn replace_cell(&mut self) -> Vec<String> {
+        vec!["readCell();".to_string()]
+    }
+
+    fn while_start(&mut self) -> Vec<String> {
+        vec!["while (cells[index] !== 0) {".to_string()]
+    }
+
+    fn while_end(&mut self) -> Vec<String> {
+        vec!["}".to_string()]
+    }
+}
+
+pub struct JavaScriptBackend;
+
+impl Backend for JavaScriptBackend {
+    fn name(&self) -> &'static str {
+        "js"
+    }
+
+    fn description(&self) -> &'static str {
+        "JavaScript module exporting a run(input: Uint8Array): Uint8Array function"
+    }
+
+    fn extension(&self) -> &'static str {
+        "mjs"
+    }
+
+    fn generate(
+        &self,
+        default_memory: usize,
+        encoding: Encoding,
+        ast: &[parser::Instruction],
+        out: &mut dyn std::io::Write,
+    ) -> std::io::Result<()> {
+        generate_javascript(default_memory, encoding, ast, out)
+    }
+}
+
+pub struct TypeScriptBackend;
+
+impl Backend for TypeScriptBackend {
+    fn name(&self) -> &'static str {
+        "ts"
+    }
+
+    fn description(&self) -> &'static str {
+        "TypeScript module exporting a run(input: Uint8Array): Uint8Array function"
+    }
+
+    fn extension(&self) -> &'static str {
+        "ts"
+    }
+
+    fn generate(
+        &self,
+        default_memory: usize,
+        encoding: Encoding,
+        ast: &[parser::Instruction],
+        out: &mut dyn std::io::Write,
+    ) -> std::io::Result<()> {
+        generate_typescript(default_memory, encoding, ast, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufWriter;
+
+    use crate::parser;
+
+    use super::*;
+
+    fn ast() -> Vec<parser::Instruction> {
+        vec![
+            parser::Instruction::IncrementCell(8),
+            parser::Instruction::While(vec![
+                parser::Instruction::MovePointerRight(1),
+                parser::Instruction::IncrementCell(4),
+            ]),
+            parser::Instruction::DisplayCell,
+        ]
+    }
+
+    #[test]
+    fn generate_proper_javascript() {
+        let mut out = BufWriter::new(Vec::new());
+        generate_javascript(4096, Encoding::Raw, &ast(), &mut out).unwrap();
+        let actual = String::from_utf8(out.into_inner().unwrap()).unwrap();
+        let expected =
+            std::fs::read_to_string("golden-files/compiler/proper_source.js.txt").unwrap();
+
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    fn generate_proper_typescript() {
+        let mut out = BufWriter::new(Vec::new());
+        generate_typescript(4096, Encoding::Raw, &ast(), &mut out).unwrap();
+        let actual = String::from_utf8(out.into_inner().unwrap()).unwrap();
+        let expected =
+            std::fs::read_to_string("golden-files/compiler/proper_source.ts.txt").unwrap();
+
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    #[ignore = "requires node"]
+    fn run_hello_world() {
+        let output = crate::compiler::run_hello_world(&JavaScriptBackend, |dir, src| {
+            let runner = dir.join("runner.mjs");
+            std::fs::write(
+                &runner,
+                format!(
+                    "import {{ run }} from './{}';
+                    process.stdout.write(run(new Uint8Array()));",
+                    src.file_name().unwrap().to_str().unwrap()
+                ),
+            )
+            .unwrap();
+
+            let mut node = std::process::Command::new("node");
+            node.arg(runner);
+            node
+        });
+
+        assert_eq!("Hello World!\n", output);
+    }
+}
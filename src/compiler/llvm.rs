@@ -0,0 +1,222 @@
+use super::Backend;
+use crate::encoding::Encoding;
+use crate::parser;
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "program.ll.txt")]
+struct ProgramTemplate<'a> {
+    program: &'a Program,
+}
+
+struct Program {
+    default_memory: usize,
+    encoding: Encoding,
+    registers: usize,
+    loops: usize,
+    body: Vec<String>,
+}
+
+impl Program {
+    pub fn new(default_memory: usize, encoding: Encoding) -> Self {
+        Program {
+            default_memory,
+            encoding,
+            registers: 0,
+            loops: 0,
+            body: Vec::new(),
+        }
+    }
+
+    fn register(&mut self) -> String {
+        self.registers += 1;
+        format!("%r{}", self.registers)
+    }
+
+    fn push(&mut self, line: String) {
+        self.body.push(format!("  {}", line));
+    }
+
+    fn push_label(&mut self, label: String) {
+        self.body.push(format!("{}:", label));
+    }
+}
+
+/// Generates a textual LLVM IR module (`.ll`) which can be compiled with `clang` or `llc`.
+/// It uses opaque pointers and relies on the C library for memory and I/O.
+pub fn generate<W: std::io::Write + ?Sized>(
+    default_memory: usize,
+    encoding: Encoding,
+    ast: &[parser::Instruction],
+    out: &mut W,
+) -> std::io::Result<()> {
+    let mut program = Program::new(default_memory, encoding);
+    do_generate(&mut program, ast);
+    write_program(&program, out)
+}
+
+fn write_program<W: std::io::Write + ?Sized>(
+    program: &Program,
+    out: &mut W,
+) -> std::io::Result<()> {
+    let tmpl = ProgramTemplate { program };
+
+    writeln!(out, "{}", tmpl.render().unwrap())
+}
+
+fn do_generate(program: &mut Program, ast: &[parser::Instruction]) {
+    for instruction in ast {
+        match instruction {
+            parser::Instruction::MovePointerRight(n) => generate_move(program, "move_right", *n),
+            parser::Instruction::MovePointerLeft(n) => generate_move(program, "move_left", *n),
+            parser::Instruction::IncrementCell(n) => generate_update(program, "add", *n),
+            parser::Instruction::DecrementCell(n) => generate_update(program, "sub", *n),
+            parser::Instruction::DisplayCell => {
+                let cell = generate_current_cell(program);
+                let value = program.register();
+                program.push(format!("{} = load i8, ptr {}", value, cell));
+                program.push(format!("call void @write_cell(i8 {})", value));
+            }
+            parser::Instruction::ReplaceCell => {
+                let cell = generate_current_cell(program);
+                program.push(format!("call void @read_cell(ptr {})", cell));
+            }
+            parser::Instruction::While(sub_ast) => {
+                program.loops += 1;
+                let id = program.loops;
+                program.push(format!("br label %loop{}.cond", id));
+                program.push_label(format!("loop{}.cond", id));
+                let cell = generate_current_cell(program);
+                let value = program.register();
+                let cond = program.register();
+                program.push(format!("{} = load i8, ptr {}", value, cell));
+                program.push(format!("{} = icmp ne i8 {}, 0", cond, value));
+                program.push(format!(
+                    "br i1 {}, label %loop{}.body, label %loop{}.end",
+                    cond, id, id
+                ));
+                program.push_label(format!("loop{}.body", id));
+                do_generate(program, sub_ast);
+                program.push(format!("br label %loop{}.cond", id));
+                program.push_label(format!("loop{}.end", id));
+            }
+        }
+    }
+}
+
+fn generate_current_cell(program: &mut Program) -> String {
+    let cell = program.register();
+    program.push(format!("{} = call ptr @current_cell(ptr %index)", cell));
+    cell
+}
+
+fn generate_move(program: &mut Program, function: &str, n: usize) {
+    let index = program.register();
+    let new_index = program.register();
+    program.push(format!("{} = load i64, ptr %index", index));
+    program.push(format!(
+        "{} = call i64 @{}(i64 {}, i64 {})",
+        new_index, function, index, n
+    ));
+    program.push(format!("store i64 {}, ptr %index", new_index));
+}
+
+fn generate_update(program: &mut Program, operation: &str, n: u8) {
+    let cell = generate_current_cell(program);
+    let value = program.register();
+    let new_value = program.register();
+    program.push(format!("{} = load i8, ptr {}", value, cell));
+    program.push(format!("{} = {} i8 {}, {}", new_value, operation, value, n));
+    program.push(format!("store i8 {}, ptr {}", new_value, cell));
+}
+
+pub struct LlvmBackend;
+
+impl Backend for LlvmBackend {
+    fn name(&self) -> &'static str {
+        "llvm"
+    }
+
+    fn description(&self) -> &'static str {
+        "LLVM IR to be compiled with clang or llc"
+    }
+
+    fn extension(&self) -> &'static str {
+        "ll"
+    }
+
+    fn generate(
+        &self,
+        default_memory: usize,
+        encoding: Encoding,
+        ast: &[parser::Instruction],
+        out: &mut dyn std::io::Write,
+    ) -> std::io::Result<()> {
+        generate(default_memory, encoding, ast, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufWriter;
+
+    use crate::parser;
+
+    use super::*;
+
+    #[test]
+    fn generate_proper_source() {
+        let ast = vec![
+            parser::Instruction::IncrementCell(8),
+            parser::Instruction::While(vec![
+                parser::Instruction::MovePointerRight(1),
+                parser::Instruction::IncrementCell(4),
+            ]),
+            parser::Instruction::DisplayCell,
+        ];
+        let mut out = BufWriter::new(Vec::new());
+        generate(4096, Encoding::Raw, &ast, &mut out).unwrap();
+        let actual = String::from_utf8(out.into_inner().unwrap()).unwrap();
+        let expected =
+            std::fs::read_to_string("golden-files/compiler/proper_source.ll.txt").unwrap();
+
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    #[ignore = "requires llc and a C compiler"]
+    fn compile_hello_world() {
+        let output = crate::compiler::run_hello_world(&LlvmBackend, |dir, src| {
+            let assembly = dir.join("hello_world.s");
+            let dest = dir.join("hello_world");
+            // opaque pointers are only enabled by default since LLVM 15
+            let version = std::process::Command::new("llc")
+                .arg("--version")
+                .output()
+                .unwrap();
+            let mut llc = std::process::Command::new("llc");
+            if String::from_utf8_lossy(&version.stdout).contains("LLVM version 14") {
+                llc.arg("-opaque-pointers");
+            }
+            let status = llc
+                .arg("-relocation-model=pic")
+                .arg("-o")
+                .arg(&assembly)
+                .arg(src)
+                .status()
+                .unwrap();
+            assert!(status.success());
+            let status = std::process::Command::new("cc")
+                .arg("-o")
+                .arg(&dest)
+                .arg(&assembly)
+                .status()
+                .unwrap();
+            assert!(status.success());
+
+            std::process::Command::new(dest)
+        });
+
+        assert_eq!("Hello World!\n", output);
+    }
+}
@@ -0,0 +1,168 @@
+use super::{lower, Backend, Lowering};
+use crate::encoding::Encoding;
+use crate::parser;
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "program.py.txt")]
+struct ProgramTemplate<'a> {
+    program: &'a Program,
+}
+
+struct Program {
+    default_memory: usize,
+    encoding: Encoding,
+    body: Vec<String>,
+}
+
+impl Program {
+    pub fn new(default_memory: usize, encoding: Encoding) -> Self {
+        Program {
+            default_memory,
+            encoding,
+            body: Vec::new(),
+        }
+    }
+}
+
+/// Generates a standalone Python 3 script, each instruction being a readable call on a
+/// `Memory` object so the program can be followed step by step.
+pub fn generate<W: std::io::Write + ?Sized>(
+    default_memory: usize,
+    encoding: Encoding,
+    ast: &[parser::Instruction],
+    out: &mut W,
+) -> std::io::Result<()> {
+    let mut program = Program::new(default_memory, encoding);
+    program.body = lower(&mut program, ast);
+    write_program(&program, out)
+}
+
+fn write_program<W: std::io::Write + ?Sized>(
+    program: &Program,
+    out: &mut W,
+) -> std::io::Result<()> {
+    let tmpl = ProgramTemplate { program };
+
+    writeln!(out, "{}", tmpl.render().unwrap())
+}
+
+impl Lowering for Program {
+    const INDENTATION: &'static str = "    ";
+
+    fn move_pointer_right(&mut self, n: usize) -> Vec<String> {
+        vec![format!("mem.move_right({})", n)]
+    }
+
+    fn move_pointer_left(&mut self, n: usize) -> Vec<String> {
+        vec![format!("mem.move_left({})", n)]
+    }
+
+    fn increment_cell(&mut self, n: u8) -> Vec<String> {
+        vec![format!("mem.increment({})", n)]
+    }
+
+    fn decrement_cell(&mut self, n: u8) -> Vec<String> {
+        vec![format!("mem.decrement({})", n)]
+    }
+
+    fn display_cell(&mut self) -> Vec<String> {
+        vec!["mem.display()".to_string()]
+    }
+
+    fn replace_cell(&mut self) -> Vec<String> {
+        vec!["mem.read()".to_string()]
+    }
+
+    fn while_start(&mut self) -> Vec<String> {
+        vec!["while mem.current() != 0:".to_string()]
+    }
+
+    fn while_end(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn empty_body(&mut self) -> Vec<String> {
+        vec!["pass".to_string()]
+    }
+}
+
+pub struct PythonBackend;
+
+impl Backend for PythonBackend {
+    fn name(&self) -> &'static str {
+        "python"
+    }
+
+    fn description(&self) -> &'static str {
+        "standalone Python 3 script"
+    }
+
+    fn extension(&self) -> &'static str {
+        "py"
+    }
+
+    fn generate(
+        &self,
+        default_memory: usize,
+        encoding: Encoding,
+        ast: &[parser::Instruction],
+        out: &mut dyn std::io::Write,
+    ) -> std::io::Result<()> {
+        generate(default_memory, encoding, ast, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufWriter;
+
+    use crate::parser;
+
+    use super::*;
+
+    #[test]
+    fn generate_proper_source() {
+        let ast = vec![
+            parser::Instruction::IncrementCell(8),
+            parser::Instruction::While(vec![
+                parser::Instruction::MovePointerRight(1),
+                parser::Instruction::IncrementCell(4),
+            ]),
+            parser::Instruction::While(vec![]),
+            parser::Instruction::DisplayCell,
+        ];
+        let mut out = BufWriter::new(Vec::new());
+        generate(4096, Encoding::Raw, &ast, &mut out).unwrap();
+        let actual = String::from_utf8(out.into_inner().unwrap()).unwrap();
+        let expected =
+            std::fs::read_to_string("golden-files/compiler/proper_source.py.txt").unwrap();
+
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    #[ignore = "requires python3"]
+    fn run_shifted_echo() {
+        let ast = parser::parse(",+.>,+.").unwrap();
+        let dir = crate::compiler::TestDir::new("python");
+        let script = dir.join("shifted_echo.py");
+        generate(
+            1,
+            Encoding::Raw,
+            &ast,
+            &mut std::fs::File::create(&script).unwrap(),
+        )
+        .unwrap();
+
+        let mut child = std::process::Command::new("python3")
+            .arg(&script)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        std::io::Write::write_all(child.stdin.as_mut().unwrap(), b"ab").unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert_eq!("bc", String::from_utf8(output.stdout).unwrap());
+    }
+}
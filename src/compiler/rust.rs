@@ -0,0 +1,503 @@
+use super::{lower, run_toolchain, Backend, CompilationError, Lowering};
+use crate::encoding::Encoding;
+use crate::parser;
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "program.rs.txt")]
+struct ProgramTemplate<'a> {
+    program: &'a Program,
+}
+
+#[derive(Template)]
+#[template(path = "library.rs.txt")]
+struct LibraryTemplate<'a> {
+    program: &'a Program,
+}
+
+#[derive(Template)]
+#[template(path = "expression.rs.txt")]
+struct ExpressionTemplate<'a> {
+    program: &'a Program,
+}
+
+struct Program {
+    default_memory: usize,
+    encoding: Encoding,
+    input_format: InputFormat,
+    body: Vec<String>,
+}
+
+impl Program {
+    pub fn new(default_memory: usize, encoding: Encoding, input_format: InputFormat) -> Self {
+        Program {
+            default_memory,
+            encoding,
+            input_format,
+            body: Vec::new(),
+        }
+    }
+}
+
+/// How the generated program reads the cells replaced by `,`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum InputFormat {
+    /// Each cell is a byte of the input.
+    #[default]
+    Bytes,
+    /// Each cell is a decimal number from 0 to 255, the numbers being separated by whitespace,
+    /// as read by `interpreter::io::Numbers`.
+    Numbers,
+}
+
+/// How `rustc` is invoked to compile the generated program.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Path to the `rustc` binary. It defaults to the `RUSTC` environment variable, or to
+    /// the `rustc` found in the `PATH`.
+    pub rustc: String,
+    /// Value of the `-C opt-level` codegen option.
+    pub opt_level: Option<String>,
+    /// Target triple the program is compiled for.
+    pub target: Option<String>,
+    /// Whether link-time optimizations are enabled.
+    pub lto: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            rustc: std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string()),
+            opt_level: None,
+            target: None,
+            lto: false,
+        }
+    }
+}
+
+pub fn compile(src: &str, dest: &str) -> Result<(), CompilationError> {
+    compile_with(src, dest, &Options::default())
+}
+
+pub fn compile_with(src: &str, dest: &str, options: &Options) -> Result<(), CompilationError> {
+    let mut command = std::process::Command::new(&options.rustc);
+    command.arg("-o").arg(dest);
+    if let Some(opt_level) = &options.opt_level {
+        command.arg("-C").arg(format!("opt-level={}", opt_level));
+    }
+    if let Some(target) = &options.target {
+        command.arg("--target").arg(target);
+    }
+    if options.lto {
+        command.arg("-C").arg("lto");
+    }
+    command.arg(src);
+
+    run_toolchain(
+        &mut command,
+        "failed to compile brainfuck Rust generated program",
+    )
+}
+
+pub fn generate<W: std::io::Write + ?Sized>(
+    default_memory: usize,
+    encoding: Encoding,
+    input_format: InputFormat,
+    ast: &[parser::Instruction],
+    out: &mut W,
+) -> std::io::Result<()> {
+    let mut program = Program::new(default_memory, encoding, input_format);
+    program.body = lower(&mut program, ast);
+    write_program(&program, out)
+}
+
+/// Generates a library exposing the program as a
+/// `pub fn run<R: Read, W: Write>(input: &mut R, output: &mut W) -> Result<(), Error>`
+/// function instead of a `main` function using the process standard input and output.
+pub fn generate_library<W: std::io::Write + ?Sized>(
+    default_memory: usize,
+    encoding: Encoding,
+    input_format: InputFormat,
+    ast: &[parser::Instruction],
+    out: &mut W,
+) -> std::io::Result<()> {
+    let mut program = Program::new(default_memory, encoding, input_format);
+    program.body = lower(&mut program, ast);
+    let tmpl = LibraryTemplate { program: &program };
+
+    writeln!(out, "{}", tmpl.render().unwrap())
+}
+
+/// Generates a block expression evaluating to the same `run` function as `generate_library`,
+/// so a program can be embedded inside Rust code, e.g. by a procedural macro. The cells are
+/// read from `input` and written to `output` as raw bytes.
+pub fn generate_expression<W: std::io::Write + ?Sized>(
+    default_memory: usize,
+    ast: &[parser::Instruction],
+    out: &mut W,
+) -> std::io::Result<()> {
+    let mut program = Program::new(default_memory, Encoding::Raw, InputFormat::Bytes);
+    program.body = lower(&mut program, ast);
+    let tmpl = ExpressionTemplate { program: &program };
+
+    writeln!(out, "{}", tmpl.render().unwrap())
+}
+
+/// Writes a crate named after the program in `dir`, its `src/lib.rs` being generated by
+/// `generate_library`.
+pub fn write_crate(
+    default_memory: usize,
+    encoding: Encoding,
+    input_format: InputFormat,
+    ast: &[parser::Instruction],
+    name: &str,
+    dir: &std::path::Path,
+) -> std::io::Result<()> {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    std::fs::create_dir_all(dir.join("src"))?;
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\n",
+            name
+        ),
+    )?;
+    let mut lib = std::fs::File::create(dir.join("src").join("lib.rs"))?;
+    generate_library(default_memory, encoding, input_format, ast, &mut lib)
+}
+
+fn write_program<W: std::io::Write + ?Sized>(
+    program: &Program,
+    out: &mut W,
+) -> std::io::Result<()> {
+    let tmpl = ProgramTemplate { program };
+
+    writeln!(out, "{}", tmpl.render().unwrap())
+}
+
+impl Lowering for Program {
+    const INDENTATION: &'static str = "";
+
+    fn move_pointer_right(&mut self, n: usize) -> Vec<String> {
+        vec![format!("mem.move_right({});", n)]
+    }
+
+    fn move_pointer_left(&mut self, n: usize) -> Vec<String> {
+        vec![format!("mem.move_left({})?;", n)]
+    }
+
+    fn increment_cell(&mut self, n: u8) -> Vec<String> {
+        vec![format!("mem.increment_cell({});", n)]
+    }
+
+    fn decrement_cell(&mut self, n: u8) -> Vec<String> {
+        vec![format!("mem.decrement_cell({});", n)]
+    }
+
+    fn display_cell(&mut self) -> Vec<String> {
+        vec!["display(&mem, output)?;".to_string()]
+    }
+
+    fn replace_cell(&mut self) -> Vec<String> {
+        vec!["read(&mut mem, input, output)?;".to_string()]
+    }
+
+    fn while_start(&mut self) -> Vec<String> {
+        vec!["while mem.current_cell_value() != 0 {".to_string()]
+    }
+
+    fn while_end(&mut self) -> Vec<String> {
+        vec!["}".to_string()]
+    }
+}
+
+#[derive(Default)]
+pub struct RustBackend {
+    options: Options,
+    input_format: InputFormat,
+}
+
+impl RustBackend {
+    pub fn new(options: Options) -> Self {
+        RustBackend {
+            options,
+            input_format: InputFormat::default(),
+        }
+    }
+
+    /// Sets how the generated programs read their input, as bytes by default.
+    pub fn input_format(mut self, input_format: InputFormat) -> Self {
+        self.input_format = input_format;
+        self
+    }
+}
+
+impl Backend for RustBackend {
+    fn name(&self) -> &'static str {
+        "rust"
+    }
+
+    fn description(&self) -> &'static str {
+        "Rust program compiled with rustc"
+    }
+
+    fn extension(&self) -> &'static str {
+        "rs"
+    }
+
+    fn toolchain(&self) -> &'static [&'static str] {
+        &["rustc"]
+    }
+
+    fn generate(
+        &self,
+        default_memory: usize,
+        encoding: Encoding,
+        ast: &[parser::Instruction],
+        out: &mut dyn std::io::Write,
+    ) -> std::io::Result<()> {
+        generate(default_memory, encoding, self.input_format, ast, out)
+    }
+
+    fn builds_executable(&self) -> bool {
+        true
+    }
+
+    fn build(&self, src: &str, dest: &str) -> Result<(), CompilationError> {
+        compile_with(src, dest, &self.options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufWriter;
+
+    use crate::{interpreter, parser};
+
+    use super::*;
+
+    #[test]
+    fn generate_proper_source() {
+        let ast = vec![
+            parser::Instruction::IncrementCell(8),
+            parser::Instruction::While(vec![
+                parser::Instruction::MovePointerRight(1),
+                parser::Instruction::IncrementCell(4),
+            ]),
+            parser::Instruction::DisplayCell,
+        ];
+        let mut out = BufWriter::new(Vec::new());
+        generate(4096, Encoding::Raw, InputFormat::Bytes, &ast, &mut out).unwrap();
+        let actual = String::from_utf8(out.into_inner().unwrap()).unwrap();
+        let expected = std::fs::read_to_string("golden-files/compiler/proper_source.txt").unwrap();
+
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    fn report_rustc_failure() {
+        let dir = std::env::temp_dir().join(format!("brainfuck-rustc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("broken.rs");
+        std::fs::write(&src, "fn main() { let x: u8 = \"not a number\"; }").unwrap();
+
+        let err = compile(src.to_str().unwrap(), dir.join("broken").to_str().unwrap()).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(Some(1), err.code());
+        assert!(err.diagnostics().contains("mismatched types"));
+    }
+
+    #[test]
+    fn report_missing_rustc() {
+        let options = Options {
+            rustc: "missing-rustc-binary".to_string(),
+            ..Options::default()
+        };
+
+        let err = compile_with("program.rs", "program", &options).unwrap_err();
+
+        assert_eq!(None, err.code());
+        assert!(err
+            .to_string()
+            .starts_with("failed to run missing-rustc-binary: "));
+    }
+
+    #[test]
+    fn negative_memory_address_like_interpreter() {
+        let ast = parser::parse("+++.<").unwrap();
+        let mut stdout = Vec::new();
+        let err = interpreter::run(16, &ast, &mut "".as_bytes(), &mut stdout).unwrap_err();
+        let dir = std::env::temp_dir().join(format!("brainfuck-rust-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("negative.rs");
+        let dest = dir.join("negative");
+        generate(
+            16,
+            Encoding::Raw,
+            InputFormat::Bytes,
+            &ast,
+            &mut std::fs::File::create(&src).unwrap(),
+        )
+        .unwrap();
+
+        compile(src.to_str().unwrap(), dest.to_str().unwrap()).unwrap();
+
+        let output = std::process::Command::new(&dest).output().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(Some(1), output.status.code());
+        assert_eq!(stdout, output.stdout);
+        assert_eq!(
+            format!("Error: {}\n", err),
+            String::from_utf8(output.stderr).unwrap()
+        );
+    }
+
+    #[test]
+    fn read_numbers_like_interpreter() {
+        let ast = parser::parse(",>,[<+>-]<.,").unwrap();
+        let dir = std::env::temp_dir().join(format!("brainfuck-numbers-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("numbers.rs");
+        let dest = dir.join("numbers");
+        generate(
+            16,
+            Encoding::Decimal,
+            InputFormat::Numbers,
+            &ast,
+            &mut std::fs::File::create(&src).unwrap(),
+        )
+        .unwrap();
+        compile(src.to_str().unwrap(), dest.to_str().unwrap()).unwrap();
+
+        for input in [" 40\n2 7", "40 2 256", "40 2 x"] {
+            let mut expected = interpreter::io::Encoded(Encoding::Decimal, Vec::new());
+            let result = interpreter::run(
+                16,
+                &ast,
+                &mut interpreter::io::Numbers(input.as_bytes()),
+                &mut expected,
+            );
+            let mut child = std::process::Command::new(&dest)
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::piped())
+                .spawn()
+                .unwrap();
+            std::io::Write::write_all(child.stdin.as_mut().unwrap(), input.as_bytes()).unwrap();
+            let output = child.wait_with_output().unwrap();
+
+            assert_eq!(b"42\n", &expected.1[..]);
+            assert_eq!(expected.1, output.stdout, "input {:?}", input);
+            let stderr = match result {
+                Ok(()) => String::new(),
+                Err(err) => format!("Error: {}\n", err),
+            };
+            assert_eq!(stderr, String::from_utf8(output.stderr).unwrap());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn generate_usable_library() {
+        let source = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.,.";
+        let ast = parser::parse(source).unwrap();
+        let mut library = Vec::new();
+        generate_library(16, Encoding::Raw, InputFormat::Bytes, &ast, &mut library).unwrap();
+        let dir = std::env::temp_dir().join(format!("brainfuck-rust-lib-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("embedded.rs");
+        let dest = dir.join("embedded");
+        std::fs::write(
+            &src,
+            format!(
+                "{}\nfn main() {{
+                    let mut output = Vec::new();
+                    run(&mut \"?\".as_bytes(), &mut output).unwrap();
+                    assert!(run(&mut \"\".as_bytes(), &mut Vec::new()).is_err());
+                    print!(\"{{}}\", String::from_utf8(output).unwrap());
+                }}",
+                String::from_utf8(library).unwrap()
+            ),
+        )
+        .unwrap();
+
+        compile(src.to_str().unwrap(), dest.to_str().unwrap()).unwrap();
+
+        let output = std::process::Command::new(&dest).output().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!("Hello World!\n?", String::from_utf8(output.stdout).unwrap());
+    }
+
+    #[test]
+    fn generate_library_without_warnings() {
+        let dir = std::env::temp_dir().join(format!("brainfuck-lib-lint-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("lib.rs");
+
+        for source in ["", "+.", ",[->+<]"] {
+            let ast = parser::parse(source).unwrap();
+            generate_library(
+                16,
+                Encoding::Raw,
+                InputFormat::Bytes,
+                &ast,
+                &mut std::fs::File::create(&src).unwrap(),
+            )
+            .unwrap();
+
+            let output = std::process::Command::new(Options::default().rustc)
+                .args([
+                    "--crate-type",
+                    "lib",
+                    "--emit",
+                    "metadata",
+                    "-D",
+                    "warnings",
+                ])
+                .arg("--out-dir")
+                .arg(&dir)
+                .arg(&src)
+                .output()
+                .unwrap();
+            assert!(
+                output.status.success(),
+                "program {:?}: {}",
+                source,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_library_crate() {
+        let dir = std::env::temp_dir().join(format!("brainfuck-crate-{}", std::process::id()));
+
+        write_crate(
+            16,
+            Encoding::Raw,
+            InputFormat::Bytes,
+            &[],
+            "tic-tac.toe",
+            &dir,
+        )
+        .unwrap();
+
+        let manifest = std::fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+        let library_exists = dir.join("src").join("lib.rs").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(manifest.contains("name = \"tic-tac_toe\""));
+        assert!(library_exists);
+    }
+}
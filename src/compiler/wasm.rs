@@ -0,0 +1,723 @@
+#[cfg(test)]
+mod validate;
+
+use super::Backend;
+use crate::encoding::Encoding;
+use crate::parser;
+
+const PAGE_SIZE: usize = 65536;
+/// Number of pages of a 32-bit memory.
+const MAX_PAGES: usize = 65536;
+
+const READ_BYTE: u32 = 0;
+const WRITE_BYTE: u32 = 1;
+const MOVE_RIGHT: u32 = 2;
+const MOVE_LEFT: u32 = 3;
+const READ_CELL: u32 = 4;
+const WRITE_CELL: u32 = 5;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Op {
+    Unreachable,
+    Block,
+    Loop,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Call(u32),
+    LocalGet(u32),
+    LocalSet(u32),
+    I32Load8U,
+    I32Store8,
+    MemorySize,
+    MemoryGrow,
+    I32Const(i32),
+    I32Eqz,
+    I32Ne,
+    I32LtU,
+    I32GtU,
+    I32GeS,
+    I32Add,
+    I32Sub,
+    I32DivU,
+    I32RemU,
+    I32And,
+    I32Or,
+    I32Shl,
+    I32ShrU,
+}
+
+impl Op {
+    fn text(&self) -> String {
+        match self {
+            Op::Unreachable => "unreachable".to_string(),
+            Op::Block => "block".to_string(),
+            Op::Loop => "loop".to_string(),
+            Op::End => "end".to_string(),
+            Op::Br(depth) => format!("br {}", depth),
+            Op::BrIf(depth) => format!("br_if {}", depth),
+            Op::Call(index) => format!("call {}", FUNCTION_NAMES[*index as usize]),
+            Op::LocalGet(index) => format!("local.get {}", index),
+            Op::LocalSet(index) => format!("local.set {}", index),
+            Op::I32Load8U => "i32.load8_u".to_string(),
+            Op::I32Store8 => "i32.store8".to_string(),
+            Op::MemorySize => "memory.size".to_string(),
+            Op::MemoryGrow => "memory.grow".to_string(),
+            Op::I32Const(n) => format!("i32.const {}", n),
+            Op::I32Eqz => "i32.eqz".to_string(),
+            Op::I32Ne => "i32.ne".to_string(),
+            Op::I32LtU => "i32.lt_u".to_string(),
+            Op::I32GtU => "i32.gt_u".to_string(),
+            Op::I32GeS => "i32.ge_s".to_string(),
+            Op::I32Add => "i32.add".to_string(),
+            Op::I32Sub => "i32.sub".to_string(),
+            Op::I32DivU => "i32.div_u".to_string(),
+            Op::I32RemU => "i32.rem_u".to_string(),
+            Op::I32And => "i32.and".to_string(),
+            Op::I32Or => "i32.or".to_string(),
+            Op::I32Shl => "i32.shl".to_string(),
+            Op::I32ShrU => "i32.shr_u".to_string(),
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Op::Unreachable => out.push(0x00),
+            Op::Block => out.extend([0x02, 0x40]),
+            Op::Loop => out.extend([0x03, 0x40]),
+            Op::End => out.push(0x0b),
+            Op::Br(depth) => {
+                out.push(0x0c);
+                write_unsigned(out, *depth as u64);
+            }
+            Op::BrIf(depth) => {
+                out.push(0x0d);
+                write_unsigned(out, *depth as u64);
+            }
+            Op::Call(index) => {
+                out.push(0x10);
+                write_unsigned(out, *index as u64);
+            }
+            Op::LocalGet(index) => {
+                out.push(0x20);
+                write_unsigned(out, *index as u64);
+            }
+            Op::LocalSet(index) => {
+                out.push(0x21);
+                write_unsigned(out, *index as u64);
+            }
+            Op::I32Load8U => out.extend([0x2d, 0x00, 0x00]),
+            Op::I32Store8 => out.extend([0x3a, 0x00, 0x00]),
+            Op::MemorySize => out.extend([0x3f, 0x00]),
+            Op::MemoryGrow => out.extend([0x40, 0x00]),
+            Op::I32Const(n) => {
+                out.push(0x41);
+                write_signed(out, *n as i64);
+            }
+            Op::I32Eqz => out.push(0x45),
+            Op::I32Ne => out.push(0x47),
+            Op::I32LtU => out.push(0x49),
+            Op::I32GtU => out.push(0x4b),
+            Op::I32GeS => out.push(0x4e),
+            Op::I32Add => out.push(0x6a),
+            Op::I32Sub => out.push(0x6b),
+            Op::I32DivU => out.push(0x6e),
+            Op::I32RemU => out.push(0x70),
+            Op::I32And => out.push(0x71),
+            Op::I32Or => out.push(0x72),
+            Op::I32Shl => out.push(0x74),
+            Op::I32ShrU => out.push(0x76),
+        }
+    }
+}
+
+const FUNCTION_NAMES: [&str; 7] = [
+    "$read_byte",
+    "$write_byte",
+    "$move_right",
+    "$move_left",
+    "$read_cell",
+    "$write_cell",
+    "$run",
+];
+
+struct Function {
+    params: usize,
+    result: bool,
+    locals: usize,
+    body: Vec<Op>,
+}
+
+impl Function {
+    fn type_index(&self) -> u32 {
+        match (self.params, self.result) {
+            (0, true) => 0,
+            (1, false) => 1,
+            (2, true) => 2,
+            _ => 3,
+        }
+    }
+}
+
+struct Module {
+    pages: usize,
+    functions: Vec<Function>,
+}
+
+impl Module {
+    fn new(
+        default_memory: usize,
+        encoding: Encoding,
+        ast: &[parser::Instruction],
+    ) -> std::io::Result<Self> {
+        let pages = std::cmp::max(1, default_memory.div_ceil(PAGE_SIZE));
+        if pages > MAX_PAGES {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "the wasm target can't allocate {} bytes of memory, its memory being limited to 4 GiB",
+                    default_memory
+                ),
+            ));
+        }
+        let mut body = Vec::new();
+        do_generate(&mut body, ast)?;
+
+        Ok(Module {
+            pages,
+            functions: vec![
+                move_right(),
+                move_left(),
+                read_cell(),
+                write_cell(encoding),
+                run(body),
+            ],
+        })
+    }
+
+    fn write_text<W: std::io::Write + ?Sized>(&self, out: &mut W) -> std::io::Result<()> {
+        writeln!(out, "(module")?;
+        writeln!(
+            out,
+            "  (import \"env\" \"read_byte\" (func $read_byte (result i32)))"
+        )?;
+        writeln!(
+            out,
+            "  (import \"env\" \"write_byte\" (func $write_byte (param i32)))"
+        )?;
+        writeln!(out, "  (memory (export \"memory\") {})", self.pages)?;
+
+        for (index, function) in self.functions.iter().enumerate() {
+            let name = FUNCTION_NAMES[index + 2];
+            write!(out, "  (func {}", name)?;
+            if name == "$run" {
+                write!(out, " (export \"run\")")?;
+            }
+            for _ in 0..function.params {
+                write!(out, " (param i32)")?;
+            }
+            if function.result {
+                write!(out, " (result i32)")?;
+            }
+            for _ in 0..function.locals {
+                write!(out, " (local i32)")?;
+            }
+            writeln!(out)?;
+
+            let mut depth = 2;
+            for op in &function.body {
+                if *op == Op::End {
+                    depth -= 1;
+                }
+                writeln!(out, "{}{}", "  ".repeat(depth), op.text())?;
+                if *op == Op::Block || *op == Op::Loop {
+                    depth += 1;
+                }
+            }
+            writeln!(out, "  )")?;
+        }
+
+        writeln!(out, ")")
+    }
+
+    fn write_binary<W: std::io::Write + ?Sized>(&self, out: &mut W) -> std::io::Result<()> {
+        let mut module = vec![0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+
+        let types: [&[u8]; 4] = [
+            &[0x60, 0x00, 0x01, 0x7f],
+            &[0x60, 0x01, 0x7f, 0x00],
+            &[0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7f],
+            &[0x60, 0x00, 0x00],
+        ];
+        let mut section = Vec::new();
+        write_unsigned(&mut section, types.len() as u64);
+        for t in types {
+            section.extend(t);
+        }
+        write_section(&mut module, 1, &section);
+
+        let mut section = Vec::new();
+        write_unsigned(&mut section, 2);
+        for (name, type_index) in [("read_byte", 0), ("write_byte", 1)] {
+            write_name(&mut section, "env");
+            write_name(&mut section, name);
+            section.push(0x00);
+            write_unsigned(&mut section, type_index);
+        }
+        write_section(&mut module, 2, &section);
+
+        let mut section = Vec::new();
+        write_unsigned(&mut section, self.functions.len() as u64);
+        for function in &self.functions {
+            write_unsigned(&mut section, function.type_index() as u64);
+        }
+        write_section(&mut module, 3, &section);
+
+        let mut section = vec![0x01, 0x00];
+        write_unsigned(&mut section, self.pages as u64);
+        write_section(&mut module, 5, &section);
+
+        let mut section = Vec::new();
+        write_unsigned(&mut section, 2);
+        write_name(&mut section, "memory");
+        section.extend([0x02, 0x00]);
+        write_name(&mut section, "run");
+        section.push(0x00);
+        write_unsigned(&mut section, (self.functions.len() + 1) as u64);
+        write_section(&mut module, 7, &section);
+
+        let mut section = Vec::new();
+        write_unsigned(&mut section, self.functions.len() as u64);
+        for function in &self.functions {
+            let mut code = Vec::new();
+            if function.locals == 0 {
+                code.push(0x00);
+            } else {
+                code.push(0x01);
+                write_unsigned(&mut code, function.locals as u64);
+                code.push(0x7f);
+            }
+            for op in &function.body {
+                op.encode(&mut code);
+            }
+            code.push(0x0b);
+            write_unsigned(&mut section, code.len() as u64);
+            section.extend(code);
+        }
+        write_section(&mut module, 10, &section);
+
+        out.write_all(&module)
+    }
+}
+
+/// Generates a WebAssembly module in its text format (WAT). The module imports
+/// `env.read_byte` and `env.write_byte`, exports its linear memory as `memory` and
+/// runs the program when calling its exported `run` function.
+///
+/// WASI isn't supported: the module doesn't import `fd_read` and `fd_write`, so it can't run
+/// in a WASI runtime such as wasmtime on its own, and the host has to provide the two
+/// functions of `env`.
+///
+/// Runtime errors (a negative memory address, the end of the input or a memory which can't grow
+/// anymore) trap with `unreachable`, without the error message of the interpreter: the host
+/// gets the trap of its engine instead.
+pub fn generate_text<W: std::io::Write + ?Sized>(
+    default_memory: usize,
+    encoding: Encoding,
+    ast: &[parser::Instruction],
+    out: &mut W,
+) -> std::io::Result<()> {
+    Module::new(default_memory, encoding, ast)?.write_text(out)
+}
+
+/// Generates the same WebAssembly module as `generate_text` in its binary format.
+pub fn generate_binary<W: std::io::Write + ?Sized>(
+    default_memory: usize,
+    encoding: Encoding,
+    ast: &[parser::Instruction],
+    out: &mut W,
+) -> std::io::Result<()> {
+    Module::new(default_memory, encoding, ast)?.write_binary(out)
+}
+
+fn move_right() -> Function {
+    Function {
+        params: 2,
+        result: true,
+        locals: 0,
+        body: vec![
+            Op::LocalGet(0),
+            Op::LocalGet(1),
+            Op::I32Add,
+            Op::LocalSet(0),
+            Op::Block,
+            Op::LocalGet(0),
+            Op::MemorySize,
+            Op::I32Const(16),
+            Op::I32Shl,
+            Op::I32LtU,
+            Op::BrIf(0),
+            Op::LocalGet(0),
+            Op::I32Const(16),
+            Op::I32ShrU,
+            Op::I32Const(1),
+            Op::I32Add,
+            Op::MemorySize,
+            Op::I32Sub,
+            Op::MemoryGrow,
+            Op::I32Const(-1),
+            Op::I32Ne,
+            Op::BrIf(0),
+            Op::Unreachable,
+            Op::End,
+            Op::LocalGet(0),
+        ],
+    }
+}
+
+fn move_left() -> Function {
+    Function {
+        params: 2,
+        result: true,
+        locals: 0,
+        body: vec![
+            Op::Block,
+            Op::LocalGet(1),
+            Op::LocalGet(0),
+            Op::I32GtU,
+            Op::I32Eqz,
+            Op::BrIf(0),
+            Op::Unreachable,
+            Op::End,
+            Op::LocalGet(0),
+            Op::LocalGet(1),
+            Op::I32Sub,
+        ],
+    }
+}
+
+fn read_cell() -> Function {
+    Function {
+        params: 1,
+        result: false,
+        locals: 1,
+        body: vec![
+            Op::Call(READ_BYTE),
+            Op::LocalSet(1),
+            Op::Block,
+            Op::LocalGet(1),
+            Op::I32Const(0),
+            Op::I32GeS,
+            Op::BrIf(0),
+            Op::Unreachable,
+            Op::End,
+            Op::LocalGet(0),
+            Op::LocalGet(1),
+            Op::I32Store8,
+        ],
+    }
+}
+
+/// Writes its parameter, a cell, through `env.write_byte` as one or more bytes.
+fn write_cell(encoding: Encoding) -> Function {
+    let body = match encoding {
+        Encoding::Raw => vec![Op::LocalGet(0), Op::Call(WRITE_BYTE)],
+        Encoding::Latin1 => vec![
+            Op::Block,
+            Op::LocalGet(0),
+            Op::I32Const(128),
+            Op::I32LtU,
+            Op::BrIf(0),
+            Op::LocalGet(0),
+            Op::I32Const(6),
+            Op::I32ShrU,
+            Op::I32Const(192),
+            Op::I32Or,
+            Op::Call(WRITE_BYTE),
+            Op::LocalGet(0),
+            Op::I32Const(63),
+            Op::I32And,
+            Op::I32Const(128),
+            Op::I32Or,
+            Op::LocalSet(0),
+            Op::End,
+            Op::LocalGet(0),
+            Op::Call(WRITE_BYTE),
+        ],
+        Encoding::Decimal => vec![
+            Op::Block,
+            Op::LocalGet(0),
+            Op::I32Const(100),
+            Op::I32LtU,
+            Op::BrIf(0),
+            Op::LocalGet(0),
+            Op::I32Const(100),
+            Op::I32DivU,
+            Op::I32Const(48),
+            Op::I32Add,
+            Op::Call(WRITE_BYTE),
+            Op::End,
+            Op::Block,
+            Op::LocalGet(0),
+            Op::I32Const(10),
+            Op::I32LtU,
+            Op::BrIf(0),
+            Op::LocalGet(0),
+            Op::I32Const(10),
+            Op::I32DivU,
+            Op::I32Const(10),
+            Op::I32RemU,
+            Op::I32Const(48),
+            Op::I32Add,
+            Op::Call(WRITE_BYTE),
+            Op::End,
+            Op::LocalGet(0),
+            Op::I32Const(10),
+            Op::I32RemU,
+            Op::I32Const(48),
+            Op::I32Add,
+            Op::Call(WRITE_BYTE),
+            Op::I32Const(10),
+            Op::Call(WRITE_BYTE),
+        ],
+    };
+
+    Function {
+        params: 1,
+        result: false,
+        locals: 0,
+        body,
+    }
+}
+
+fn run(body: Vec<Op>) -> Function {
+    Function {
+        params: 0,
+        result: false,
+        locals: 1,
+        body,
+    }
+}
+
+/// Turns the length of a move into an immediate, the addresses of the module being 32-bit.
+fn move_length(n: usize) -> std::io::Result<i32> {
+    i32::try_from(n).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("the wasm target can't move the pointer by {} cells", n),
+        )
+    })
+}
+
+fn do_generate(body: &mut Vec<Op>, ast: &[parser::Instruction]) -> std::io::Result<()> {
+    for instruction in ast {
+        match instruction {
+            parser::Instruction::MovePointerRight(n) => body.extend([
+                Op::LocalGet(0),
+                Op::I32Const(move_length(*n)?),
+                Op::Call(MOVE_RIGHT),
+                Op::LocalSet(0),
+            ]),
+            parser::Instruction::MovePointerLeft(n) => body.extend([
+                Op::LocalGet(0),
+                Op::I32Const(move_length(*n)?),
+                Op::Call(MOVE_LEFT),
+                Op::LocalSet(0),
+            ]),
+            parser::Instruction::IncrementCell(n) => body.extend([
+                Op::LocalGet(0),
+                Op::LocalGet(0),
+                Op::I32Load8U,
+                Op::I32Const(i32::from(*n)),
+                Op::I32Add,
+                Op::I32Store8,
+            ]),
+            parser::Instruction::DecrementCell(n) => body.extend([
+                Op::LocalGet(0),
+                Op::LocalGet(0),
+                Op::I32Load8U,
+                Op::I32Const(i32::from(*n)),
+                Op::I32Sub,
+                Op::I32Store8,
+            ]),
+            parser::Instruction::DisplayCell => {
+                body.extend([Op::LocalGet(0), Op::I32Load8U, Op::Call(WRITE_CELL)])
+            }
+            parser::Instruction::ReplaceCell => body.extend([Op::LocalGet(0), Op::Call(READ_CELL)]),
+            parser::Instruction::While(sub_ast) => {
+                body.extend([
+                    Op::Block,
+                    Op::Loop,
+                    Op::LocalGet(0),
+                    Op::I32Load8U,
+                    Op::I32Eqz,
+                    Op::BrIf(1),
+                ]);
+                do_generate(body, sub_ast)?;
+                body.extend([Op::Br(0), Op::End, Op::End]);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn write_section(module: &mut Vec<u8>, id: u8, content: &[u8]) {
+    module.push(id);
+    write_unsigned(module, content.len() as u64);
+    module.extend(content);
+}
+
+fn write_name(out: &mut Vec<u8>, name: &str) {
+    write_unsigned(out, name.len() as u64);
+    out.extend(name.as_bytes());
+}
+
+fn write_unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+pub struct WasmBackend;
+
+impl Backend for WasmBackend {
+    fn name(&self) -> &'static str {
+        "wasm"
+    }
+
+    fn description(&self) -> &'static str {
+        "WebAssembly module importing env.read_byte and env.write_byte"
+    }
+
+    fn extension(&self) -> &'static str {
+        "wasm"
+    }
+
+    fn generate(
+        &self,
+        default_memory: usize,
+        encoding: Encoding,
+        ast: &[parser::Instruction],
+        out: &mut dyn std::io::Write,
+    ) -> std::io::Result<()> {
+        generate_binary(default_memory, encoding, ast, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufWriter;
+
+    use crate::parser;
+
+    use super::*;
+
+    #[test]
+    fn generate_proper_text() {
+        let ast = vec![
+            parser::Instruction::IncrementCell(8),
+            parser::Instruction::While(vec![
+                parser::Instruction::MovePointerRight(1),
+                parser::Instruction::IncrementCell(4),
+            ]),
+            parser::Instruction::DisplayCell,
+        ];
+        let mut out = BufWriter::new(Vec::new());
+        generate_text(4096, Encoding::Raw, &ast, &mut out).unwrap();
+        let actual = String::from_utf8(out.into_inner().unwrap()).unwrap();
+        let expected =
+            std::fs::read_to_string("golden-files/compiler/proper_source.wat.txt").unwrap();
+
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    fn reject_moves_beyond_32_bits() {
+        let ast = vec![parser::Instruction::MovePointerRight(1 << 31)];
+        let err = generate_binary(512, Encoding::Raw, &ast, &mut Vec::new()).unwrap_err();
+
+        assert_eq!(std::io::ErrorKind::InvalidInput, err.kind());
+        assert_eq!(
+            "the wasm target can't move the pointer by 2147483648 cells",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn reject_memory_beyond_32_bits() {
+        let ast = vec![parser::Instruction::DisplayCell];
+        generate_binary(1 << 32, Encoding::Raw, &ast, &mut Vec::new()).unwrap();
+        let err = generate_binary((1 << 32) + 1, Encoding::Raw, &ast, &mut Vec::new()).unwrap_err();
+
+        assert_eq!(std::io::ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn signed_leb128() {
+        let mut out = Vec::new();
+        write_signed(&mut out, -1);
+        write_signed(&mut out, 64);
+        write_signed(&mut out, 63);
+
+        assert_eq!(vec![0x7f, 0xc0, 0x00, 0x3f], out)
+    }
+
+    #[test]
+    fn generate_valid_modules() {
+        for entry in std::fs::read_dir("examples").unwrap() {
+            let source = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            let ast = parser::parse(&source).unwrap();
+            for encoding in Encoding::ALL {
+                let mut out = Vec::new();
+                generate_binary(30000, encoding, &ast, &mut out).unwrap();
+
+                assert_eq!(Ok(()), validate::validate(&out));
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "requires node"]
+    fn run_hello_world_binary() {
+        let output = crate::compiler::run_hello_world(&WasmBackend, |dir, src| {
+            let runner = dir.join("runner.js");
+            std::fs::write(
+                &runner,
+                "const bytes = require('fs').readFileSync(process.argv[2]);
+                const output = [];
+                const env = { read_byte: () => -1, write_byte: (b) => output.push(b) };
+                WebAssembly.instantiate(bytes, { env }).then(({ instance }) => {
+                    instance.exports.run();
+                    process.stdout.write(Buffer.from(output));
+                });",
+            )
+            .unwrap();
+
+            let mut node = std::process::Command::new("node");
+            node.arg(runner).arg(src);
+            node
+        });
+
+        assert_eq!("Hello World!\n", output);
+    }
+}
@@ -0,0 +1,590 @@
+//! Validation of WebAssembly modules in their binary format, for the tests to check the modules
+//! generated by the backend without any runtime.
+//!
+//! The structure of the module and the typing of the function bodies follow the validation
+//! algorithm of the specification. Only the sections and the instructions the backend may
+//! generate are supported, anything else being reported as an error.
+
+const I32: u8 = 0x7f;
+const VALUE_TYPES: [u8; 4] = [0x7f, 0x7e, 0x7d, 0x7c];
+const MAX_PAGES: u64 = 65536;
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ValidationError(String);
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid WebAssembly module: {}", self.0)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+fn error<T>(message: impl Into<String>) -> Result<T, ValidationError> {
+    Err(ValidationError(message.into()))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+
+    fn byte(&mut self) -> Result<u8, ValidationError> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or_else(|| ValidationError("unexpected end".to_string()))?;
+        self.position += 1;
+
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], ValidationError> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position.saturating_add(length))
+            .ok_or_else(|| ValidationError("unexpected end".to_string()))?;
+        self.position += length;
+
+        Ok(bytes)
+    }
+
+    fn unsigned(&mut self, bits: u32) -> Result<u64, ValidationError> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            if shift >= bits {
+                return error("integer too long");
+            }
+        }
+        if bits < 64 && value >> bits != 0 {
+            return error("integer too large");
+        }
+
+        Ok(value)
+    }
+
+    fn u32(&mut self) -> Result<u32, ValidationError> {
+        Ok(self.unsigned(32)? as u32)
+    }
+
+    fn length(&mut self) -> Result<usize, ValidationError> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn i32(&mut self) -> Result<i32, ValidationError> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            value |= i64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                break;
+            }
+            if shift >= 35 {
+                return error("integer too long");
+            }
+        }
+
+        i32::try_from(value).or_else(|_| error("integer too large"))
+    }
+
+    fn name(&mut self) -> Result<&'a str, ValidationError> {
+        let length = self.length()?;
+        std::str::from_utf8(self.bytes(length)?).or_else(|_| error("invalid UTF-8 name"))
+    }
+
+    fn value_type(&mut self) -> Result<u8, ValidationError> {
+        let byte = self.byte()?;
+        if !VALUE_TYPES.contains(&byte) {
+            return error(format!("unknown value type 0x{:02x}", byte));
+        }
+
+        Ok(byte)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct FunctionType {
+    params: Vec<u8>,
+    results: Vec<u8>,
+}
+
+/// Checks that `module` is a valid WebAssembly module.
+pub fn validate(module: &[u8]) -> Result<(), ValidationError> {
+    let mut reader = Reader::new(module);
+    if reader.bytes(8).ok() != Some(&[0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00][..]) {
+        return error("expected the header of a version 1 module");
+    }
+
+    let mut types = Vec::new();
+    let mut functions: Vec<u32> = Vec::new();
+    let mut imported = 0;
+    let mut memories = 0;
+    let mut declared = 0;
+    let mut defined = None;
+    let mut last_section = 0;
+    while !reader.is_empty() {
+        let id = reader.byte()?;
+        let length = reader.length()?;
+        let mut section = Reader::new(reader.bytes(length)?);
+        if id == 0 {
+            section.name()?;
+            continue;
+        }
+        if id <= last_section {
+            return error(format!("section {} out of order", id));
+        }
+        last_section = id;
+
+        match id {
+            1 => {
+                for _ in 0..section.length()? {
+                    if section.byte()? != 0x60 {
+                        return error("expected a function type");
+                    }
+                    let params = (0..section.length()?)
+                        .map(|_| section.value_type())
+                        .collect::<Result<_, _>>()?;
+                    let results: Vec<u8> = (0..section.length()?)
+                        .map(|_| section.value_type())
+                        .collect::<Result<_, _>>()?;
+                    if results.len() > 1 {
+                        return error("functions return at most one value");
+                    }
+                    types.push(FunctionType { params, results });
+                }
+            }
+            2 => {
+                for _ in 0..section.length()? {
+                    section.name()?;
+                    section.name()?;
+                    if section.byte()? != 0x00 {
+                        return error("only functions can be imported");
+                    }
+                    functions.push(type_index(&mut section, &types)?);
+                    imported += 1;
+                }
+            }
+            3 => {
+                for _ in 0..section.length()? {
+                    functions.push(type_index(&mut section, &types)?);
+                    declared += 1;
+                }
+            }
+            5 => {
+                for _ in 0..section.length()? {
+                    let (min, max) = match section.byte()? {
+                        0x00 => (section.u32()? as u64, None),
+                        0x01 => (section.u32()? as u64, Some(section.u32()? as u64)),
+                        flags => return error(format!("unknown limits 0x{:02x}", flags)),
+                    };
+                    if min > MAX_PAGES || max.is_some_and(|max| max > MAX_PAGES || max < min) {
+                        return error("invalid memory limits");
+                    }
+                    memories += 1;
+                }
+                if memories > 1 {
+                    return error("multiple memories");
+                }
+            }
+            7 => {
+                let mut names = std::collections::HashSet::new();
+                for _ in 0..section.length()? {
+                    let name = section.name()?;
+                    if !names.insert(name) {
+                        return error(format!("duplicate export {}", name));
+                    }
+                    let kind = section.byte()?;
+                    let index = section.u32()? as usize;
+                    let count = match kind {
+                        0x00 => functions.len(),
+                        0x02 => memories,
+                        _ => return error(format!("unsupported export kind 0x{:02x}", kind)),
+                    };
+                    if index >= count {
+                        return error(format!("unknown export {}", name));
+                    }
+                }
+            }
+            10 => {
+                let count = section.length()?;
+                if count != declared {
+                    return error("function and code sections don't match");
+                }
+                for index in 0..count {
+                    let length = section.length()?;
+                    let mut code = Reader::new(section.bytes(length)?);
+                    let signature = &types[functions[imported + index] as usize];
+                    validate_code(&mut code, signature, &types, &functions, memories > 0).map_err(
+                        |err| ValidationError(format!("function {}: {}", imported + index, err.0)),
+                    )?;
+                }
+                defined = Some(count);
+            }
+            _ => return error(format!("unsupported section {}", id)),
+        }
+        if !section.is_empty() {
+            return error(format!("section {} longer than its content", id));
+        }
+    }
+    if defined.unwrap_or(0) != declared {
+        return error("function and code sections don't match");
+    }
+
+    Ok(())
+}
+
+fn type_index(reader: &mut Reader, types: &[FunctionType]) -> Result<u32, ValidationError> {
+    let index = reader.u32()?;
+    if index as usize >= types.len() {
+        return error(format!("unknown type {}", index));
+    }
+
+    Ok(index)
+}
+
+/// Block being validated, along with the height of the operand stack when it started.
+struct Frame {
+    is_loop: bool,
+    results: Vec<u8>,
+    height: usize,
+    unreachable: bool,
+}
+
+impl Frame {
+    /// Types of the values given to a branch to this frame.
+    fn label_types(&self) -> Vec<u8> {
+        if self.is_loop {
+            Vec::new()
+        } else {
+            self.results.clone()
+        }
+    }
+}
+
+/// Operand and control stacks of the function being validated.
+struct Stacks {
+    operands: Vec<u8>,
+    frames: Vec<Frame>,
+}
+
+impl Stacks {
+    fn push(&mut self, value_type: u8) {
+        self.operands.push(value_type);
+    }
+
+    /// Pops an operand, returning `None` for a value of any type in unreachable code.
+    fn pop(&mut self) -> Result<Option<u8>, ValidationError> {
+        let frame = self
+            .frames
+            .last()
+            .expect("frames are checked by the caller");
+        if self.operands.len() == frame.height {
+            if frame.unreachable {
+                return Ok(None);
+            }
+            return error("missing operand");
+        }
+
+        Ok(self.operands.pop())
+    }
+
+    fn pop_expected(&mut self, expected: u8) -> Result<(), ValidationError> {
+        match self.pop()? {
+            Some(actual) if actual != expected => error(format!(
+                "expected an operand of type 0x{:02x}, got 0x{:02x}",
+                expected, actual
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn pop_all(&mut self, expected: &[u8]) -> Result<(), ValidationError> {
+        for value_type in expected.iter().rev() {
+            self.pop_expected(*value_type)?;
+        }
+
+        Ok(())
+    }
+
+    fn label(&self, depth: u32) -> Result<Vec<u8>, ValidationError> {
+        match self.frames.len().checked_sub(depth as usize + 1) {
+            Some(index) => Ok(self.frames[index].label_types()),
+            None => error(format!("unknown label {}", depth)),
+        }
+    }
+
+    fn set_unreachable(&mut self) {
+        let frame = self
+            .frames
+            .last_mut()
+            .expect("frames are checked by the caller");
+        self.operands.truncate(frame.height);
+        frame.unreachable = true;
+    }
+}
+
+fn validate_code(
+    code: &mut Reader,
+    signature: &FunctionType,
+    types: &[FunctionType],
+    functions: &[u32],
+    has_memory: bool,
+) -> Result<(), ValidationError> {
+    let mut locals = signature.params.clone();
+    for _ in 0..code.length()? {
+        let count = code.u32()? as usize;
+        let value_type = code.value_type()?;
+        if locals.len() + count > u32::MAX as usize {
+            return error("too many locals");
+        }
+        locals.extend(std::iter::repeat_n(value_type, count));
+    }
+
+    let mut stacks = Stacks {
+        operands: Vec::new(),
+        frames: vec![Frame {
+            is_loop: false,
+            results: signature.results.clone(),
+            height: 0,
+            unreachable: false,
+        }],
+    };
+    let memory = |code: &mut Reader, alignment: u32| -> Result<(), ValidationError> {
+        if !has_memory {
+            return error("memory instruction without memory");
+        }
+        if code.u32()? > alignment {
+            return error("alignment larger than natural");
+        }
+        code.u32()?;
+
+        Ok(())
+    };
+    while !stacks.frames.is_empty() {
+        let opcode = code.byte()?;
+        match opcode {
+            // unreachable
+            0x00 => stacks.set_unreachable(),
+            // block, loop
+            0x02 | 0x03 => {
+                let results = match code.byte()? {
+                    0x40 => Vec::new(),
+                    byte if VALUE_TYPES.contains(&byte) => vec![byte],
+                    byte => return error(format!("unsupported block type 0x{:02x}", byte)),
+                };
+                stacks.frames.push(Frame {
+                    is_loop: opcode == 0x03,
+                    results,
+                    height: stacks.operands.len(),
+                    unreachable: false,
+                });
+            }
+            // end
+            0x0b => {
+                let results = stacks.frames.last().map(|frame| frame.results.clone());
+                let results = results.unwrap_or_default();
+                stacks.pop_all(&results)?;
+                let frame = stacks.frames.pop().expect("the loop stops without frames");
+                if stacks.operands.len() != frame.height {
+                    return error("values left on the stack at the end of a block");
+                }
+                stacks.operands.extend(results);
+            }
+            // br
+            0x0c => {
+                let label = stacks.label(code.u32()?)?;
+                stacks.pop_all(&label)?;
+                stacks.set_unreachable();
+            }
+            // br_if
+            0x0d => {
+                let label = stacks.label(code.u32()?)?;
+                stacks.pop_expected(I32)?;
+                stacks.pop_all(&label)?;
+                stacks.operands.extend(label);
+            }
+            // call
+            0x10 => {
+                let index = code.u32()? as usize;
+                let callee = functions
+                    .get(index)
+                    .map(|type_index| &types[*type_index as usize])
+                    .ok_or_else(|| ValidationError(format!("unknown function {}", index)))?;
+                stacks.pop_all(&callee.params)?;
+                stacks.operands.extend(&callee.results);
+            }
+            // drop
+            0x1a => {
+                stacks.pop()?;
+            }
+            // local.get, local.set, local.tee
+            0x20..=0x22 => {
+                let index = code.u32()? as usize;
+                let value_type = *locals
+                    .get(index)
+                    .ok_or_else(|| ValidationError(format!("unknown local {}", index)))?;
+                if opcode != 0x20 {
+                    stacks.pop_expected(value_type)?;
+                }
+                if opcode != 0x21 {
+                    stacks.push(value_type);
+                }
+            }
+            // i32.load8_u
+            0x2d => {
+                memory(code, 0)?;
+                stacks.pop_expected(I32)?;
+                stacks.push(I32);
+            }
+            // i32.store8
+            0x3a => {
+                memory(code, 0)?;
+                stacks.pop_expected(I32)?;
+                stacks.pop_expected(I32)?;
+            }
+            // memory.size, memory.grow
+            0x3f | 0x40 => {
+                if !has_memory {
+                    return error("memory instruction without memory");
+                }
+                if code.byte()? != 0x00 {
+                    return error("expected the memory index 0");
+                }
+                if opcode == 0x40 {
+                    stacks.pop_expected(I32)?;
+                }
+                stacks.push(I32);
+            }
+            // i32.const
+            0x41 => {
+                code.i32()?;
+                stacks.push(I32);
+            }
+            // i32.eqz, i32.clz, i32.ctz, i32.popcnt
+            0x45 | 0x67..=0x69 => {
+                stacks.pop_expected(I32)?;
+                stacks.push(I32);
+            }
+            // i32 comparisons and binary operators
+            0x46..=0x4f | 0x6a..=0x78 => {
+                stacks.pop_expected(I32)?;
+                stacks.pop_expected(I32)?;
+                stacks.push(I32);
+            }
+            _ => return error(format!("unsupported instruction 0x{:02x}", opcode)),
+        }
+    }
+    if !code.is_empty() {
+        return error("instructions after the end of the function");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Function, Module, Op};
+    use super::*;
+
+    fn module(body: Vec<Op>) -> Vec<u8> {
+        let function = Function {
+            params: 0,
+            result: false,
+            locals: 1,
+            body,
+        };
+        let mut out = Vec::new();
+        Module {
+            pages: 1,
+            functions: vec![function],
+        }
+        .write_binary(&mut out)
+        .unwrap();
+
+        out
+    }
+
+    #[test]
+    fn accept_valid_modules() {
+        let body = vec![
+            Op::Block,
+            Op::Loop,
+            Op::LocalGet(0),
+            Op::I32Load8U,
+            Op::I32Eqz,
+            Op::BrIf(1),
+            Op::Call(0),
+            Op::LocalGet(0),
+            Op::I32Add,
+            Op::LocalSet(0),
+            Op::Br(0),
+            Op::End,
+            Op::End,
+        ];
+
+        assert_eq!(Ok(()), validate(&module(body)));
+        assert_eq!(
+            Ok(()),
+            validate(&module(vec![Op::Unreachable, Op::I32Add, Op::LocalSet(0)]))
+        );
+    }
+
+    #[test]
+    fn reject_invalid_modules() {
+        for (body, message) in [
+            (
+                vec![Op::I32Const(1), Op::I32Add],
+                "function 2: missing operand",
+            ),
+            (
+                vec![Op::I32Const(1)],
+                "function 2: values left on the stack at the end of a block",
+            ),
+            (vec![Op::Br(1)], "function 2: unknown label 1"),
+            (vec![Op::LocalGet(1)], "function 2: unknown local 1"),
+            (vec![Op::Call(7)], "function 2: unknown function 7"),
+            (vec![Op::Block], "function 2: unexpected end"),
+            (
+                vec![Op::End, Op::End],
+                "function 2: instructions after the end of the function",
+            ),
+            (vec![Op::Call(1)], "function 2: missing operand"),
+        ] {
+            let err = validate(&module(body)).unwrap_err();
+            assert_eq!(
+                format!("invalid WebAssembly module: {}", message),
+                err.to_string()
+            );
+        }
+
+        let err = validate(b"\0asm\x02\0\0\0").unwrap_err();
+        assert_eq!(
+            "invalid WebAssembly module: expected the header of a version 1 module",
+            err.to_string()
+        );
+        let mut truncated = module(vec![]);
+        truncated.pop();
+        assert!(validate(&truncated).is_err());
+    }
+}
@@ -0,0 +1,491 @@
+use std::collections::BTreeSet;
+
+use crate::{inspect, interpreter, parser, repl};
+
+const HELP: &str = "\
+step [n]       run the next n instructions, 1 by default, stopping at watchpoints
+continue       run until a breakpoint or a watchpoint is reached or the program is over
+rstep [n]      step back over the last n instructions, 1 by default
+rcontinue      step back until a breakpoint or the start of the recording is reached
+lastwrite <i>  print when the cell at index i was last written
+rwrite <i>     step back to the last write to the cell at index i
+break [line]   pause before the instruction at this line of the listing, or list the breakpoints
+delete <line>  remove a breakpoint
+watch [i [v]]  pause when the cell at index i is written, or only when it becomes equal to v,
+               or list the watchpoints
+unwatch <i>    remove the watchpoints on the cell at index i
+list           print the instructions around the next one
+tape           print the tape and the pointer
+restart        clear the tape and go back to the first instruction
+help           print this message
+quit           leave the debugger
+";
+
+/// Number of instructions printed by `list` before and after the next one.
+const CONTEXT: usize = 3;
+
+#[derive(Debug)]
+pub struct DebuggerError(String);
+
+impl std::fmt::Display for DebuggerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for DebuggerError {}
+
+impl From<std::io::Error> for DebuggerError {
+    fn from(err: std::io::Error) -> Self {
+        Self(err.to_string())
+    }
+}
+
+impl From<interpreter::RuntimeError> for DebuggerError {
+    fn from(err: interpreter::RuntimeError) -> Self {
+        Self(err.to_string())
+    }
+}
+
+/// Runs a program step by step, pausing on breakpoints. Instructions are identified by their
+/// line in the listing written by `inspect::disassemble`.
+///
+/// The machine records the changes made by the instructions, so the debugger can also go
+/// backwards.
+pub struct Debugger<R, W> {
+    machine: interpreter::Machine<R, W>,
+    listing: Vec<String>,
+    breakpoints: BTreeSet<usize>,
+}
+
+impl<R: interpreter::io::Input, W: interpreter::io::Output> Debugger<R, W> {
+    pub fn new(default_memory: usize, ast: &[parser::Instruction], input: R, output: W) -> Self {
+        let mut listing = Vec::new();
+        inspect::disassemble(ast, &mut listing).expect("failed to list the instructions");
+        let listing = String::from_utf8(listing)
+            .expect("listing is not valid UTF-8")
+            .lines()
+            .map(String::from)
+            .collect();
+
+        let mut machine = interpreter::Machine::new(default_memory, ast, input, output);
+        machine.set_recording(true);
+
+        Self {
+            machine,
+            listing,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn machine(&self) -> &interpreter::Machine<R, W> {
+        &self.machine
+    }
+
+    /// Writes where the program is paused.
+    pub fn location<O: std::io::Write>(&self, out: &mut O) -> std::io::Result<()> {
+        match self.listing.get(self.machine.pc()) {
+            Some(line) => writeln!(out, "{}", line),
+            None => writeln!(out, "program finished after {} steps", self.machine.steps()),
+        }
+    }
+
+    /// Evaluates a debugger command, writing its result to `out`.
+    pub fn eval<O: std::io::Write>(
+        &mut self,
+        line: &str,
+        out: &mut O,
+    ) -> Result<repl::Status, DebuggerError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let command = words.first().copied().unwrap_or("");
+        let arguments = words.get(1..).unwrap_or_default();
+        let result = self.do_eval(command, arguments, out);
+        // The output of the program is flushed before anything else is printed.
+        self.machine.output_mut().flush()?;
+        let status = result?;
+        if matches!(
+            command,
+            "step"
+                | "s"
+                | "continue"
+                | "c"
+                | "rstep"
+                | "rs"
+                | "rcontinue"
+                | "rc"
+                | "rwrite"
+                | "rw"
+                | "restart"
+        ) {
+            self.location(out)?;
+        }
+
+        Ok(status)
+    }
+
+    fn do_eval<O: std::io::Write>(
+        &mut self,
+        command: &str,
+        arguments: &[&str],
+        out: &mut O,
+    ) -> Result<repl::Status, DebuggerError> {
+        let argument = arguments.first().copied();
+        match command {
+            "" => {}
+            "step" | "s" => {
+                let count = match argument {
+                    Some(count) => parse_number(count)?,
+                    None => 1,
+                };
+                for _ in 0..count {
+                    if !self.machine.step()? || self.machine.triggered().is_some() {
+                        break;
+                    }
+                }
+                self.watchpoint(out)?;
+            }
+            "continue" | "c" => {
+                let breakpoints = &self.breakpoints;
+                if self.machine.run_until(|machine| {
+                    breakpoints.contains(&machine.pc()) || machine.triggered().is_some()
+                })? && !self.watchpoint(out)?
+                {
+                    write!(out, "breakpoint: ")?;
+                }
+            }
+            "rstep" | "rs" => {
+                let count = match argument {
+                    Some(count) => parse_number(count)?,
+                    None => 1,
+                };
+                if !self.machine.step_back() {
+                    return Err(DebuggerError(
+                        "no instruction to step back over".to_string(),
+                    ));
+                }
+                for _ in 1..count {
+                    if !self.machine.step_back() {
+                        break;
+                    }
+                }
+            }
+            "rcontinue" | "rc" => {
+                let breakpoints = &self.breakpoints;
+                if self
+                    .machine
+                    .run_back_until(|machine| breakpoints.contains(&machine.pc()))
+                {
+                    write!(out, "breakpoint: ")?;
+                } else {
+                    write!(out, "start of the recording: ")?;
+                }
+            }
+            "lastwrite" | "lw" => {
+                let index = parse_number(argument.unwrap_or(""))?;
+                match self.machine.last_write(index) {
+                    Some(write) => writeln!(
+                        out,
+                        "cell {} written at step {}, {:02x} -> {:02x}, by {}",
+                        index, write.step, write.previous, write.value, self.listing[write.pc]
+                    )?,
+                    None => writeln!(
+                        out,
+                        "cell {} not written since the recording started",
+                        index
+                    )?,
+                }
+            }
+            "rwrite" | "rw" => {
+                let index = parse_number(argument.unwrap_or(""))?;
+                let write = self.machine.last_write(index).ok_or_else(|| {
+                    DebuggerError(format!(
+                        "cell {} not written since the recording started",
+                        index
+                    ))
+                })?;
+                self.machine
+                    .run_back_until(|machine| machine.steps() < write.step);
+            }
+            "break" | "b" => match argument {
+                Some(line) => {
+                    let line = parse_number(line)?;
+                    if line >= self.listing.len() {
+                        return Err(DebuggerError(format!("no instruction at line {}", line)));
+                    }
+                    self.breakpoints.insert(line);
+                }
+                None => {
+                    for line in &self.breakpoints {
+                        writeln!(out, "{}", self.listing[*line])?;
+                    }
+                }
+            },
+            "delete" | "d" => {
+                let line = parse_number(argument.unwrap_or(""))?;
+                if !self.breakpoints.remove(&line) {
+                    return Err(DebuggerError(format!("no breakpoint at line {}", line)));
+                }
+            }
+            "list" | "l" => {
+                let pc = self.machine.pc();
+                let start = pc.saturating_sub(CONTEXT);
+                let end = (pc + CONTEXT + 1).min(self.listing.len());
+                for line in start..end {
+                    let marker = if line == pc { "=>" } else { "  " };
+                    writeln!(out, "{} {}", marker, self.listing[line])?;
+                }
+                if self.machine.is_halted() {
+                    self.location(out)?;
+                }
+            }
+            "watch" | "w" => match argument {
+                Some(index) => {
+                    let index = parse_number(index)?;
+                    let watchpoint = match arguments.get(1) {
+                        Some(value) => interpreter::Watchpoint::Equals(
+                            index,
+                            value.parse().map_err(|_| {
+                                DebuggerError(format!("expected a byte, got '{}'", value))
+                            })?,
+                        ),
+                        None => interpreter::Watchpoint::Write(index),
+                    };
+                    self.machine.watch(watchpoint);
+                }
+                None => {
+                    let mut watchpoints = self.machine.watchpoints().to_vec();
+                    watchpoints.sort();
+                    for watchpoint in watchpoints {
+                        writeln!(out, "{}", watchpoint)?;
+                    }
+                }
+            },
+            "unwatch" | "u" => {
+                let index = parse_number(argument.unwrap_or(""))?;
+                let watched: Vec<_> = self
+                    .machine
+                    .watchpoints()
+                    .iter()
+                    .copied()
+                    .filter(|watchpoint| watchpoint.index() == index)
+                    .collect();
+                if watched.is_empty() {
+                    return Err(DebuggerError(format!("no watchpoint on cell {}", index)));
+                }
+                for watchpoint in watched {
+                    self.machine.unwatch(watchpoint);
+                }
+            }
+            "tape" | "t" => {
+                inspect::dump_tape(self.machine.tape(), self.machine.pointer(), out)?;
+            }
+            "restart" => self.machine.reset(),
+            "help" | "h" => write!(out, "{}", HELP)?,
+            "quit" | "q" => return Ok(repl::Status::Exit),
+            _ => {
+                return Err(DebuggerError(format!(
+                    "unknown command {}, try help",
+                    command
+                )))
+            }
+        }
+
+        Ok(repl::Status::Continue)
+    }
+}
+
+impl<R, W> Debugger<R, W> {
+    /// Writes the watchpoint hit by the last instruction, if any, before the location.
+    fn watchpoint<O: std::io::Write>(&self, out: &mut O) -> std::io::Result<bool> {
+        match self.machine.triggered() {
+            Some((watchpoint, write)) => {
+                write!(
+                    out,
+                    "{}, {:02x} -> {:02x}: ",
+                    watchpoint, write.previous, write.value
+                )?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Result<usize, DebuggerError> {
+    text.parse()
+        .map_err(|_| DebuggerError(format!("expected a number, got '{}'", text)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer;
+
+    fn debugger(source: &str) -> Debugger<&'static [u8], Vec<u8>> {
+        let ast = optimizer::shrink_calls(&parser::parse(source).unwrap());
+
+        Debugger::new(16, &ast, "".as_bytes(), Vec::new())
+    }
+
+    fn eval<R: interpreter::io::Input>(
+        debugger: &mut Debugger<R, Vec<u8>>,
+        line: &str,
+    ) -> Result<String, DebuggerError> {
+        let mut out = Vec::new();
+        debugger.eval(line, &mut out)?;
+
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn step_through_program() {
+        let mut debugger = debugger("++[>+<-]>.");
+
+        assert_eq!("0001  loop\n", eval(&mut debugger, "step").unwrap());
+        assert_eq!("0004      left 1\n", eval(&mut debugger, "s 3").unwrap());
+        assert_eq!(
+            "   0001  loop\n   0002      right 1\n   0003      inc 1\n=> 0004      left 1\n   0005      dec 1\n   0006  end\n   0007  right 1\n",
+            eval(&mut debugger, "list").unwrap()
+        );
+        assert_eq!(
+            "pointer: 1\n0000  02 [01]\n",
+            eval(&mut debugger, "tape").unwrap()
+        );
+        assert_eq!(
+            "program finished after 14 steps\n",
+            eval(&mut debugger, "continue").unwrap()
+        );
+        assert_eq!(vec![2], *debugger.machine().output());
+        assert_eq!("0000  inc 2\n", eval(&mut debugger, "restart").unwrap());
+    }
+
+    #[test]
+    fn pause_on_breakpoints() {
+        let mut debugger = debugger("++[>+<-]>.");
+
+        eval(&mut debugger, "break 5").unwrap();
+        eval(&mut debugger, "b 8").unwrap();
+        assert_eq!(
+            "0005      dec 1\n0008  out\n",
+            eval(&mut debugger, "break").unwrap()
+        );
+        assert_eq!(
+            "breakpoint: 0005      dec 1\n",
+            eval(&mut debugger, "c").unwrap()
+        );
+        eval(&mut debugger, "delete 5").unwrap();
+        assert_eq!(
+            "breakpoint: 0008  out\n",
+            eval(&mut debugger, "continue").unwrap()
+        );
+        assert!(debugger.machine().output().is_empty());
+
+        let err = eval(&mut debugger, "break 9").unwrap_err();
+        assert_eq!("no instruction at line 9", err.to_string());
+        let err = eval(&mut debugger, "delete 5").unwrap_err();
+        assert_eq!("no breakpoint at line 5", err.to_string());
+    }
+
+    #[test]
+    fn step_backwards() {
+        let mut debugger = debugger("++[>+<-]>.");
+
+        eval(&mut debugger, "break 4").unwrap();
+        eval(&mut debugger, "continue").unwrap();
+        eval(&mut debugger, "continue").unwrap();
+        assert_eq!(
+            "cell 1 written at step 9, 01 -> 02, by 0003      inc 1\n",
+            eval(&mut debugger, "lastwrite 1").unwrap()
+        );
+        assert_eq!(
+            "0003      inc 1\n",
+            eval(&mut debugger, "rwrite 1").unwrap()
+        );
+        assert_eq!(
+            "pointer: 1\n0000  01 [01]\n",
+            eval(&mut debugger, "tape").unwrap()
+        );
+        assert_eq!("0006  end\n", eval(&mut debugger, "rs 2").unwrap());
+        assert_eq!(
+            "breakpoint: 0004      left 1\n",
+            eval(&mut debugger, "rcontinue").unwrap()
+        );
+        eval(&mut debugger, "delete 4").unwrap();
+        assert_eq!(
+            "start of the recording: 0000  inc 2\n",
+            eval(&mut debugger, "rc").unwrap()
+        );
+        let err = eval(&mut debugger, "rstep").unwrap_err();
+        assert_eq!("no instruction to step back over", err.to_string());
+        assert_eq!(
+            "cell 1 not written since the recording started\n",
+            eval(&mut debugger, "lastwrite 1").unwrap()
+        );
+        eval(&mut debugger, "break 4").unwrap();
+        eval(&mut debugger, "c").unwrap();
+        eval(&mut debugger, "c").unwrap();
+        assert_eq!(
+            "breakpoint: 0004      left 1\n",
+            eval(&mut debugger, "rc").unwrap()
+        );
+    }
+
+    #[test]
+    fn pause_on_watchpoints() {
+        let mut debugger = debugger("++[>+<-]>.");
+
+        eval(&mut debugger, "watch 1 2").unwrap();
+        eval(&mut debugger, "w 0").unwrap();
+        assert_eq!(
+            "write to cell 0\ncell 1 equal to 2\n",
+            eval(&mut debugger, "watch").unwrap()
+        );
+        assert_eq!(
+            "write to cell 0, 00 -> 02: 0001  loop\n",
+            eval(&mut debugger, "continue").unwrap()
+        );
+        eval(&mut debugger, "unwatch 0").unwrap();
+        assert_eq!(
+            "cell 1 equal to 2, 01 -> 02: 0004      left 1\n",
+            eval(&mut debugger, "c").unwrap()
+        );
+        assert_eq!(
+            "program finished after 14 steps\n",
+            eval(&mut debugger, "step 10").unwrap()
+        );
+
+        eval(&mut debugger, "restart").unwrap();
+        eval(&mut debugger, "watch 0").unwrap();
+        assert_eq!(
+            "write to cell 0, 00 -> 02: 0001  loop\n",
+            eval(&mut debugger, "step 3").unwrap()
+        );
+
+        eval(&mut debugger, "unwatch 0").unwrap();
+        let err = eval(&mut debugger, "unwatch 0").unwrap_err();
+        assert_eq!("no watchpoint on cell 0", err.to_string());
+        let err = eval(&mut debugger, "watch 1 256").unwrap_err();
+        assert_eq!("expected a byte, got '256'", err.to_string());
+    }
+
+    #[test]
+    fn report_errors() {
+        let mut debugger = debugger("+<");
+
+        let err = eval(&mut debugger, "continue").unwrap_err();
+        assert_eq!(
+            "failed during execution: negative memory address are invalid",
+            err.to_string()
+        );
+        assert_eq!(
+            "   0000  inc 1\n=> 0001  left 1\n",
+            eval(&mut debugger, "list").unwrap()
+        );
+        let err = eval(&mut debugger, "step x").unwrap_err();
+        assert_eq!("expected a number, got 'x'", err.to_string());
+        let err = eval(&mut debugger, "jump").unwrap_err();
+        assert_eq!("unknown command jump, try help", err.to_string());
+        let mut out = Vec::new();
+        assert_eq!(repl::Status::Exit, debugger.eval("quit", &mut out).unwrap());
+    }
+}
@@ -0,0 +1,112 @@
+//! How the cells written by `.` are turned into bytes, by the interpreter as well as by the
+//! programs generated by every backend.
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Encoding {
+    /// The cell is written as a single byte.
+    #[default]
+    Raw,
+    /// The cell is the code point of a character written in UTF-8: cells from 128 to 255 take
+    /// two bytes.
+    Latin1,
+    /// The value of the cell is written in decimal, followed by a new line.
+    Decimal,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 3] = [Encoding::Raw, Encoding::Latin1, Encoding::Decimal];
+
+    /// Name used to select the encoding from the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Raw => "raw",
+            Encoding::Latin1 => "latin1",
+            Encoding::Decimal => "decimal",
+        }
+    }
+
+    /// Writes the bytes written for `cell` at the start of `buffer`, which is large enough for
+    /// every encoding, and returns them.
+    pub fn encode<'a>(&self, cell: u8, buffer: &'a mut [u8; 4]) -> &'a [u8] {
+        match self {
+            Encoding::Raw => {
+                buffer[0] = cell;
+                &buffer[..1]
+            }
+            Encoding::Latin1 => (cell as char).encode_utf8(buffer).as_bytes(),
+            Encoding::Decimal => {
+                buffer[3] = b'\n';
+                let mut start = 3;
+                let mut value = cell;
+                loop {
+                    start -= 1;
+                    buffer[start] = b'0' + value % 10;
+                    value /= 10;
+                    if value == 0 {
+                        break;
+                    }
+                }
+                &buffer[start..]
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl std::str::FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Encoding::ALL
+            .into_iter()
+            .find(|encoding| encoding.name() == name)
+            .ok_or_else(|| format!("unknown encoding {}", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_cells() {
+        for (encoding, expected) in [
+            (Encoding::Raw, vec![b'A', 0xe9]),
+            (Encoding::Latin1, "Aé".as_bytes().to_vec()),
+            (Encoding::Decimal, b"65\n233\n".to_vec()),
+        ] {
+            let mut out = Vec::new();
+            for cell in [b'A', 0xe9] {
+                out.extend(encoding.encode(cell, &mut [0; 4]));
+            }
+
+            assert_eq!(expected, out, "encoding {}", encoding);
+        }
+    }
+
+    #[test]
+    fn encode_decimal_cells() {
+        for (cell, expected) in [(0, "0\n"), (7, "7\n"), (42, "42\n"), (255, "255\n")] {
+            assert_eq!(
+                expected.as_bytes(),
+                Encoding::Decimal.encode(cell, &mut [0; 4])
+            );
+        }
+    }
+
+    #[test]
+    fn parse_names() {
+        for encoding in Encoding::ALL {
+            assert_eq!(Ok(encoding), encoding.name().parse());
+        }
+        assert_eq!(
+            Err("unknown encoding utf8".to_string()),
+            "utf8".parse::<Encoding>()
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use crate::parser;
+
+/// Figures describing the shape of a program.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Statistics {
+    /// Brainfuck commands, brackets included, the AST stands for.
+    pub commands: usize,
+    /// Nodes of the AST, each run of identical commands being a single instruction once
+    /// optimized.
+    pub instructions: usize,
+    pub loops: usize,
+    pub max_depth: usize,
+    pub inputs: usize,
+    pub outputs: usize,
+}
+
+impl Statistics {
+    pub fn of(ast: &[parser::Instruction]) -> Self {
+        let mut stats = Self::default();
+        stats.collect(ast, 0);
+
+        stats
+    }
+
+    fn collect(&mut self, ast: &[parser::Instruction], depth: usize) {
+        self.max_depth = self.max_depth.max(depth);
+        for instruction in ast {
+            self.instructions += 1;
+            match instruction {
+                parser::Instruction::MovePointerRight(n)
+                | parser::Instruction::MovePointerLeft(n) => self.commands += n,
+                parser::Instruction::IncrementCell(n) | parser::Instruction::DecrementCell(n) => {
+                    self.commands += *n as usize
+                }
+                parser::Instruction::DisplayCell => {
+                    self.commands += 1;
+                    self.outputs += 1;
+                }
+                parser::Instruction::ReplaceCell => {
+                    self.commands += 1;
+                    self.inputs += 1;
+                }
+                parser::Instruction::While(sub_ast) => {
+                    self.commands += 2;
+                    self.loops += 1;
+                    self.collect(sub_ast, depth + 1);
+                }
+            }
+        }
+    }
+}
+
+/// Writes the instructions of the AST one per line, loop bodies being indented.
+pub fn disassemble<W: std::io::Write + ?Sized>(
+    ast: &[parser::Instruction],
+    out: &mut W,
+) -> std::io::Result<()> {
+    let mut line = 0;
+    do_disassemble(ast, 0, &mut line, out)
+}
+
+fn do_disassemble<W: std::io::Write + ?Sized>(
+    ast: &[parser::Instruction],
+    depth: usize,
+    line: &mut usize,
+    out: &mut W,
+) -> std::io::Result<()> {
+    let indentation = "    ".repeat(depth);
+    for instruction in ast {
+        let text = match instruction {
+            parser::Instruction::MovePointerRight(n) => format!("right {}", n),
+            parser::Instruction::MovePointerLeft(n) => format!("left {}", n),
+            parser::Instruction::IncrementCell(n) => format!("inc {}", n),
+            parser::Instruction::DecrementCell(n) => format!("dec {}", n),
+            parser::Instruction::DisplayCell => "out".to_string(),
+            parser::Instruction::ReplaceCell => "in".to_string(),
+            parser::Instruction::While(_) => "loop".to_string(),
+        };
+        writeln!(out, "{:04}  {}{}", line, indentation, text)?;
+        *line += 1;
+        if let parser::Instruction::While(sub_ast) = instruction {
+            do_disassemble(sub_ast, depth + 1, line, out)?;
+            writeln!(out, "{:04}  {}end", line, indentation)?;
+            *line += 1;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the cells of the tape up to the last one which is either set or pointed at, 16 per
+/// line, the pointed cell being surrounded with brackets.
+pub fn dump_tape<W: std::io::Write + ?Sized>(
+    cells: &[u8],
+    pointer: usize,
+    out: &mut W,
+) -> std::io::Result<()> {
+    let used = cells
+        .iter()
+        .rposition(|&cell| cell != 0)
+        .map_or(0, |last| last + 1)
+        .max(pointer + 1)
+        .min(cells.len());
+    writeln!(out, "pointer: {}", pointer)?;
+    for (row, chunk) in cells[..used].chunks(16).enumerate() {
+        let mut line = format!("{:04x} ", row * 16);
+        for (column, cell) in chunk.iter().enumerate() {
+            if row * 16 + column == pointer {
+                line.push_str(&format!("[{:02x}]", cell));
+            } else {
+                line.push_str(&format!(" {:02x} ", cell));
+            }
+        }
+        writeln!(out, "{}", line.trim_end())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer;
+
+    #[test]
+    fn statistics() {
+        let ast = parser::parse("++[>+<-]>[[,.]]").unwrap();
+
+        let raw = Statistics::of(&ast);
+        let optimized = Statistics::of(&optimizer::shrink_calls(&ast));
+
+        assert_eq!(
+            Statistics {
+                commands: 15,
+                instructions: 12,
+                loops: 3,
+                max_depth: 2,
+                inputs: 1,
+                outputs: 1,
+            },
+            raw
+        );
+        assert_eq!(15, optimized.commands);
+        assert_eq!(11, optimized.instructions);
+    }
+
+    #[test]
+    fn disassembled_listing() {
+        let ast = optimizer::shrink_calls(&parser::parse("++[>+<-].").unwrap());
+        let mut out = Vec::new();
+
+        disassemble(&ast, &mut out).unwrap();
+
+        assert_eq!(
+            "0000  inc 2\n0001  loop\n0002      right 1\n0003      inc 1\n0004      left 1\n0005      dec 1\n0006  end\n0007  out\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn dumped_tape() {
+        let mut cells = vec![0; 32];
+        cells[0] = 0x48;
+        cells[17] = 0xff;
+        let mut out = Vec::new();
+
+        dump_tape(&cells, 1, &mut out).unwrap();
+
+        assert_eq!(
+            "pointer: 1\n0000  48 [00] 00  00  00  00  00  00  00  00  00  00  00  00  00  00\n0010  00  ff\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
@@ -1,6 +1,11 @@
 use crate::parser;
 
-#[derive(Debug)]
+pub mod io;
+pub mod snapshot;
+
+use io::{Input, Output};
+
+#[derive(Debug, Eq, PartialEq)]
 pub struct RuntimeError(&'static str);
 
 impl std::fmt::Display for RuntimeError {
@@ -11,7 +16,19 @@ impl std::fmt::Display for RuntimeError {
 
 impl std::error::Error for RuntimeError {}
 
-struct Memory {
+impl RuntimeError {
+    /// Error of an instruction interrupted by a signal before it could run, e.g. while waiting
+    /// for its input. The instruction can be run again.
+    const INTERRUPTED: RuntimeError = RuntimeError("interrupted by a signal");
+
+    pub fn is_interrupted(&self) -> bool {
+        *self == RuntimeError::INTERRUPTED
+    }
+}
+
+/// Tape of the interpreter, along with the pointer to its current cell.
+#[derive(Clone)]
+pub struct Memory {
     cells: Vec<u8>,
     index: usize,
 }
@@ -68,53 +85,507 @@ impl Memory {
     pub fn set_current_cell_value(&mut self, value: u8) {
         self.cells[self.index] = value;
     }
+
+    pub fn cells(&self) -> &[u8] {
+        &self.cells
+    }
+
+    pub fn pointer(&self) -> usize {
+        self.index
+    }
 }
 
-pub fn run<R: std::io::BufRead, W: std::io::Write>(
+pub fn run<R: Input + ?Sized, W: Output + ?Sized>(
     memory: usize,
-    ast: &Vec<parser::Instruction>,
+    ast: &[parser::Instruction],
     stdin: &mut R,
     stdout: &mut W,
 ) -> Result<(), RuntimeError> {
-    let mut memory = Memory::new(memory);
-
-    execute(&mut memory, ast, stdin, stdout)
+    Machine::new(memory, ast, stdin, stdout).run()
 }
 
-fn execute<R: std::io::BufRead, W: std::io::Write>(
+/// Runs the AST against an existing memory, which keeps its state once the program is done (or
+/// failed), so it can be inspected or used by another program.
+pub fn execute<R: Input + ?Sized, W: Output + ?Sized>(
     memory: &mut Memory,
-    ast: &Vec<parser::Instruction>,
+    ast: &[parser::Instruction],
     stdin: &mut R,
     stdout: &mut W,
 ) -> Result<(), RuntimeError> {
-    let mut buffer: [u8; 1] = [0];
+    let mut machine = Machine::new(memory.cells.len(), ast, stdin, stdout);
+    machine.memory = std::mem::replace(memory, Memory::new(0));
+    let result = machine.run();
+    *memory = machine.memory;
+
+    result
+}
 
+/// Instruction of the flattened program run by `Machine`, loops being turned into jumps so that
+/// their position matches the line numbers of `inspect::disassemble`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Op {
+    MovePointerRight(usize),
+    MovePointerLeft(usize),
+    IncrementCell(u8),
+    DecrementCell(u8),
+    DisplayCell,
+    ReplaceCell,
+    /// Start of a loop, jumping past its end when the current cell is zero.
+    LoopStart(usize),
+    /// End of a loop, jumping back into its body unless the current cell is zero.
+    LoopEnd(usize),
+}
+
+fn flatten(ast: &[parser::Instruction], program: &mut Vec<Op>) {
     for instruction in ast {
         match instruction {
-            parser::Instruction::MovePointerRight(n) => memory.next_cell(*n),
-            parser::Instruction::MovePointerLeft(n) => memory.previous_cell(*n)?,
-            parser::Instruction::IncrementCell(n) => memory.increment_cell(*n),
-            parser::Instruction::DecrementCell(n) => memory.decrement_cell(*n),
-            parser::Instruction::DisplayCell => {
-                if write!(stdout, "{}", memory.current_cell_value() as char).is_err() {
+            parser::Instruction::MovePointerRight(n) => program.push(Op::MovePointerRight(*n)),
+            parser::Instruction::MovePointerLeft(n) => program.push(Op::MovePointerLeft(*n)),
+            parser::Instruction::IncrementCell(n) => program.push(Op::IncrementCell(*n)),
+            parser::Instruction::DecrementCell(n) => program.push(Op::DecrementCell(*n)),
+            parser::Instruction::DisplayCell => program.push(Op::DisplayCell),
+            parser::Instruction::ReplaceCell => program.push(Op::ReplaceCell),
+            parser::Instruction::While(sub_ast) => {
+                let start = program.len();
+                program.push(Op::LoopStart(0));
+                flatten(sub_ast, program);
+                program[start] = Op::LoopStart(program.len());
+                program.push(Op::LoopEnd(start));
+            }
+        }
+    }
+}
+
+/// Number of instructions a recording `Machine` can step back over.
+const RECORDING_LIMIT: usize = 1 << 20;
+
+/// Change made by an instruction, recorded to be undone.
+#[derive(Clone, Copy, Debug)]
+struct Delta {
+    pc: usize,
+    pointer: usize,
+    /// Value of the current cell before the instruction, if it was written.
+    previous: Option<u8>,
+    /// Byte read from the input by the instruction.
+    read: Option<u8>,
+}
+
+/// Write to a cell of the tape, as recorded by a `Machine`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CellWrite {
+    /// Number of instructions run once the write was done.
+    pub step: u64,
+    /// Position of the instruction which wrote the cell.
+    pub pc: usize,
+    pub previous: u8,
+    pub value: u8,
+}
+
+/// Condition on a cell of the tape, checked each time an instruction writes the cell.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Watchpoint {
+    /// Any write, even one leaving the value unchanged.
+    Write(usize),
+    /// Write leaving the cell equal to the value.
+    Equals(usize, u8),
+}
+
+impl Watchpoint {
+    pub fn index(&self) -> usize {
+        match self {
+            Watchpoint::Write(index) | Watchpoint::Equals(index, _) => *index,
+        }
+    }
+
+    fn matches(&self, index: usize, value: u8) -> bool {
+        match self {
+            Watchpoint::Write(watched) => *watched == index,
+            Watchpoint::Equals(watched, expected) => *watched == index && *expected == value,
+        }
+    }
+}
+
+impl std::fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Watchpoint::Write(index) => write!(f, "write to cell {}", index),
+            Watchpoint::Equals(index, value) => write!(f, "cell {} equal to {}", index, value),
+        }
+    }
+}
+
+/// Interpreter which can be paused and inspected between two instructions.
+///
+/// Instructions are numbered the same way as the lines written by `inspect::disassemble`, the
+/// start and the end of each loop being instructions of their own.
+///
+/// While recording, the changes made by the last instructions are kept, so that the machine can
+/// step back over them. The output written by these instructions is not taken back.
+///
+/// Watchpoints tell which writes to the tape should pause the machine: `run_watching` calls
+/// back when one of them is hit, and `triggered` gives the one hit by the last instruction.
+pub struct Machine<R, W> {
+    default_memory: usize,
+    program: Vec<Op>,
+    memory: Memory,
+    pc: usize,
+    steps: u64,
+    input_offset: u64,
+    history: Option<std::collections::VecDeque<Delta>>,
+    /// Bytes given back to the input when stepping back, read again before the actual input.
+    replay: Vec<u8>,
+    watchpoints: Vec<Watchpoint>,
+    triggered: Option<(Watchpoint, CellWrite)>,
+    input: R,
+    output: W,
+}
+
+impl<R: Input, W: Output> Machine<R, W> {
+    pub fn new(default_memory: usize, ast: &[parser::Instruction], input: R, output: W) -> Self {
+        let mut program = Vec::new();
+        flatten(ast, &mut program);
+
+        Self {
+            default_memory,
+            program,
+            memory: Memory::new(default_memory),
+            pc: 0,
+            steps: 0,
+            input_offset: 0,
+            history: None,
+            replay: Vec::new(),
+            watchpoints: Vec::new(),
+            triggered: None,
+            input,
+            output,
+        }
+    }
+
+    /// Runs the next instruction, or returns `false` if the program is already over. The machine
+    /// is left untouched when the instruction fails.
+    pub fn step(&mut self) -> Result<bool, RuntimeError> {
+        self.advance::<true>()
+    }
+
+    /// Runs the next instruction, tracking its writes for the history and the watchpoints only
+    /// when `OBSERVED`, so that plain runs don't pay for them.
+    #[inline(always)]
+    fn advance<const OBSERVED: bool>(&mut self) -> Result<bool, RuntimeError> {
+        let op = match self.program.get(self.pc) {
+            Some(op) => *op,
+            None => return Ok(false),
+        };
+        let mut next = self.pc + 1;
+        let mut delta = Delta {
+            pc: self.pc,
+            pointer: self.memory.index,
+            previous: None,
+            read: None,
+        };
+        match op {
+            Op::MovePointerRight(n) => self.memory.next_cell(n),
+            Op::MovePointerLeft(n) => self.memory.previous_cell(n)?,
+            Op::IncrementCell(n) => {
+                if OBSERVED {
+                    delta.previous = Some(self.memory.current_cell_value());
+                }
+                self.memory.increment_cell(n)
+            }
+            Op::DecrementCell(n) => {
+                if OBSERVED {
+                    delta.previous = Some(self.memory.current_cell_value());
+                }
+                self.memory.decrement_cell(n)
+            }
+            Op::DisplayCell => {
+                if self
+                    .output
+                    .write_byte(self.memory.current_cell_value())
+                    .is_err()
+                {
                     return Err(RuntimeError("cannot write to stdout"));
                 }
             }
-            parser::Instruction::ReplaceCell => {
-                if stdin.read_exact(&mut buffer).is_err() {
-                    return Err(RuntimeError("cannot read STDIN"));
+            Op::ReplaceCell => {
+                let byte = match self.replay.pop() {
+                    Some(byte) => byte,
+                    None => match self.input.read_byte() {
+                        Ok(Some(byte)) => byte,
+                        Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {
+                            return Err(RuntimeError::INTERRUPTED)
+                        }
+                        _ => return Err(RuntimeError("cannot read STDIN")),
+                    },
+                };
+                delta.previous = Some(self.memory.current_cell_value());
+                delta.read = Some(byte);
+                self.memory.set_current_cell_value(byte);
+                self.input_offset += 1;
+            }
+            Op::LoopStart(end) => {
+                if self.memory.current_cell_value() == 0 {
+                    next = end + 1;
                 }
-                memory.set_current_cell_value(buffer[0])
             }
-            parser::Instruction::While(sub_ast) => {
-                while memory.current_cell_value() != 0 {
-                    execute(memory, sub_ast, stdin, stdout)?
+            Op::LoopEnd(start) => {
+                if self.memory.current_cell_value() != 0 {
+                    next = start + 1;
                 }
             }
         }
+        self.pc = next;
+        self.steps += 1;
+        if !OBSERVED {
+            return Ok(true);
+        }
+        self.triggered = delta.previous.and_then(|previous| {
+            let value = self.memory.cells[delta.pointer];
+            let watchpoint = self
+                .watchpoints
+                .iter()
+                .find(|watchpoint| watchpoint.matches(delta.pointer, value))?;
+            let write = CellWrite {
+                step: self.steps,
+                pc: delta.pc,
+                previous,
+                value,
+            };
+
+            Some((*watchpoint, write))
+        });
+        if let Some(history) = &mut self.history {
+            if history.len() == RECORDING_LIMIT {
+                history.pop_front();
+            }
+            history.push_back(delta);
+        }
+
+        Ok(true)
+    }
+
+    /// Runs the program until it's over.
+    pub fn run(&mut self) -> Result<(), RuntimeError> {
+        if self.history.is_some() || !self.watchpoints.is_empty() {
+            self.run_until(|_| false)?;
+            return Ok(());
+        }
+
+        self.triggered = None;
+        loop {
+            match self.advance::<false>() {
+                Ok(true) => {}
+                Ok(false) => return Ok(()),
+                Err(err) if err.is_interrupted() => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Runs instructions until `stop` returns `true`, `stop` being checked after each of them,
+    /// as well as when an instruction is interrupted by a signal, the instruction being run
+    /// again otherwise. Returns `false` if the program ended first.
+    pub fn run_until<F: FnMut(&Self) -> bool>(
+        &mut self,
+        mut stop: F,
+    ) -> Result<bool, RuntimeError> {
+        loop {
+            match self.step() {
+                Ok(true) => {}
+                Ok(false) => return Ok(false),
+                Err(err) if err.is_interrupted() => {}
+                Err(err) => return Err(err),
+            }
+            if stop(self) {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Runs instructions until `on_watchpoint` returns `true`, `on_watchpoint` being called
+    /// after each instruction hitting a watchpoint. Returns `false` if the program ended first.
+    pub fn run_watching<F: FnMut(&Self, Watchpoint, CellWrite) -> bool>(
+        &mut self,
+        mut on_watchpoint: F,
+    ) -> Result<bool, RuntimeError> {
+        self.run_until(|machine| match machine.triggered {
+            Some((watchpoint, write)) => on_watchpoint(machine, watchpoint, write),
+            None => false,
+        })
+    }
+
+    /// Clears the tape and moves back to the first instruction, the I/O handles being kept.
+    pub fn reset(&mut self) {
+        self.memory = Memory::new(self.default_memory);
+        self.pc = 0;
+        self.steps = 0;
+        self.triggered = None;
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+    }
+
+    /// Captures the state of the machine, to be resumed later with `from_snapshot`.
+    pub fn snapshot(&self) -> snapshot::Snapshot {
+        snapshot::Snapshot {
+            default_memory: self.default_memory,
+            program: self.program.clone(),
+            cells: self.memory.cells.clone(),
+            pointer: self.memory.index,
+            pc: self.pc,
+            steps: self.steps,
+            input_offset: self.input_offset,
+        }
+    }
+
+    /// Resumes the program of a snapshot where it was captured. `input` is read from its current
+    /// position: skipping the bytes the program already read is up to the caller.
+    pub fn from_snapshot(snapshot: snapshot::Snapshot, input: R, output: W) -> Self {
+        Self {
+            default_memory: snapshot.default_memory,
+            program: snapshot.program,
+            memory: Memory {
+                cells: snapshot.cells,
+                index: snapshot.pointer,
+            },
+            pc: snapshot.pc,
+            steps: snapshot.steps,
+            input_offset: snapshot.input_offset,
+            history: None,
+            replay: Vec::new(),
+            watchpoints: Vec::new(),
+            triggered: None,
+            input,
+            output,
+        }
+    }
+}
+
+impl<R, W> Machine<R, W> {
+    pub fn tape(&self) -> &[u8] {
+        self.memory.cells()
+    }
+
+    pub fn pointer(&self) -> usize {
+        self.memory.pointer()
+    }
+
+    /// Position of the next instruction.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Number of instructions run since the start.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Number of bytes read from the input since the machine was created, resets included.
+    pub fn input_offset(&self) -> u64 {
+        self.input_offset
+    }
+
+    /// Starts or stops recording the changes made by the instructions, the ones already recorded
+    /// being forgotten when stopping.
+    pub fn set_recording(&mut self, recording: bool) {
+        match (recording, &self.history) {
+            (true, None) => self.history = Some(std::collections::VecDeque::new()),
+            (false, Some(_)) => self.history = None,
+            _ => {}
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.history.is_some()
+    }
+
+    /// Undoes the last recorded instruction, or returns `false` if there is none.
+    pub fn step_back(&mut self) -> bool {
+        let delta = match self.history.as_mut().and_then(|history| history.pop_back()) {
+            Some(delta) => delta,
+            None => return false,
+        };
+        self.pc = delta.pc;
+        self.memory.index = delta.pointer;
+        if let Some(previous) = delta.previous {
+            self.memory.set_current_cell_value(previous);
+        }
+        if let Some(byte) = delta.read {
+            self.replay.push(byte);
+            self.input_offset -= 1;
+        }
+        self.steps -= 1;
+        self.triggered = None;
+
+        true
+    }
+
+    /// Steps back until `stop` returns `true`, `stop` being checked after each instruction
+    /// undone. Returns `false` if the start of the recording was reached first.
+    pub fn run_back_until<F: FnMut(&Self) -> bool>(&mut self, mut stop: F) -> bool {
+        while self.step_back() {
+            if stop(self) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Last recorded write to the cell at `index`.
+    pub fn last_write(&self, index: usize) -> Option<CellWrite> {
+        let history = self.history.as_ref()?;
+        let position = history
+            .iter()
+            .rposition(|delta| delta.pointer == index && delta.previous.is_some())?;
+        let delta = history[position];
+
+        Some(CellWrite {
+            step: self.steps - (history.len() - position) as u64 + 1,
+            pc: delta.pc,
+            previous: delta.previous.unwrap_or_default(),
+            // No later write changed the cell.
+            value: self.memory.cells[index],
+        })
+    }
+
+    /// Adds a watchpoint, or returns `false` if it was already set.
+    pub fn watch(&mut self, watchpoint: Watchpoint) -> bool {
+        if self.watchpoints.contains(&watchpoint) {
+            return false;
+        }
+        self.watchpoints.push(watchpoint);
+
+        true
+    }
+
+    /// Removes a watchpoint, or returns `false` if it wasn't set.
+    pub fn unwatch(&mut self, watchpoint: Watchpoint) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|watched| *watched != watchpoint);
+
+        self.watchpoints.len() < count
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Watchpoint hit by the last instruction, with the write which hit it.
+    pub fn triggered(&self) -> Option<(Watchpoint, CellWrite)> {
+        self.triggered
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.pc >= self.program.len()
+    }
+
+    pub fn output(&self) -> &W {
+        &self.output
     }
 
-    Ok(())
+    pub fn output_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
 }
 
 #[cfg(test)]
@@ -127,11 +598,11 @@ mod tests {
         let source = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
         let ast = parser::parse(source).unwrap();
         let mut stdin = "".as_bytes();
-        let mut output = std::io::BufWriter::new(Vec::new());
+        let mut output = io::Bytes(std::io::BufWriter::new(Vec::new()));
 
         run(512, &ast, &mut stdin, &mut output).unwrap();
 
-        let output = String::from_utf8(output.into_inner().unwrap()).unwrap();
+        let output = String::from_utf8(output.0.into_inner().unwrap()).unwrap();
         assert_eq!("Hello World!\n".to_string(), output);
     }
 
@@ -140,14 +611,260 @@ mod tests {
         let source = "--->--->>>>->->->>>>>-->>>>>>>>>>>>>>>>>>+>>++++++++++[<<++[--<+<<+<<+>>>>[>[<->>+++>>[-]+++<<<+[<++>>+<--]]+>+++++[>>+++++++++<<-]>>++++.[-]>>+[<<<<+>>+>>-]<<<<<<[>+<-]<<]++++++++++.[-]>++]-->>[-->[-]>]<<[>>--[-[-[-----[>+>+++++++<<+]-->>-.----->,[<->-]<[[<]+[->>]<-]<[<<,[-]]>>>>]>]<[>-[+<+++]+<+++[+[---->]+<<<<<<[>>]<[-]]>[<+[---->]++[<]<[>]>[[>]+>+++++++++<<-[<]]]>[>>>>]]<[-[[>+>+<<-]>[<+>-]++>+>>]<[<<++[-->>[-]]>[[-]>[<<+>>-]>]]]<[[[<<]-[>>]<+<-]>[-<+]<<[<<]-<[>[+>>]>[>]>[-]]>[[+>>]<-->>[>]+>>>]]<[-[--[+<<<<--[+>[-]>[<<+>+>-]<<[>>+<<-]]++[>]]<<[>+>+<<-]>--[<+>-]++>>>]<[<<<[-]+++>[-]>[<+>>>+<<-]+>>>]]<[+[[<]<<[<<]-<->>+>[>>]>[>]<-]+[-<+]<++[[>+<-]++<[<<->>+]<++]<<<<<<<+>>>+>>>+[<<<->+>+>+[<<<<<<<+>->+>>>->->+[<<<<<->+>+>>+>+[<<<<->->+>->+[<<<<<<<<+>->>+>>>->+>+[<<<<<->>+>>->+[<<<<+>->+>>+]]]]]]]+++[[>+<-]<+++]--->>[-[<->-]<++>>]++[[<->-]>>]>[>]]<]]<]";
         let ast = parser::parse(source).unwrap();
         let mut stdin = "5\n7\n6\n".as_bytes();
-        let mut output = std::io::BufWriter::new(Vec::new());
+        let mut output = io::Bytes(std::io::BufWriter::new(Vec::new()));
 
         run(512, &ast, &mut stdin, &mut output).unwrap();
 
-        let output = String::from_utf8(output.into_inner().unwrap()).unwrap();
+        let output = String::from_utf8(output.0.into_inner().unwrap()).unwrap();
         assert_eq!(
             "X23\n456\n789\n>X23\n4O6\n78X\n>X2X\n4O6\nO8X\n>XXX\n4OO\nO8X\n".to_string(),
             output
         );
     }
+
+    #[test]
+    fn write_exact_bytes() {
+        let ast = parser::parse(",+.,.").unwrap();
+        let mut input = std::collections::VecDeque::from(vec![0xe8, 0xff]);
+        let mut output = Vec::new();
+
+        run(16, &ast, &mut input, &mut output).unwrap();
+        assert_eq!(vec![0xe9, 0xff], output);
+
+        let mut written = Vec::new();
+        let mut machine = Machine::new(
+            16,
+            &ast,
+            io::InputFn(|| Some(1)),
+            io::OutputFn(|byte| written.push(byte)),
+        );
+        machine.run().unwrap();
+        drop(machine);
+        assert_eq!(vec![2, 1], written);
+
+        let err = run(16, &ast, &mut "".as_bytes(), &mut Vec::new()).unwrap_err();
+        assert_eq!(RuntimeError("cannot read STDIN"), err);
+    }
+
+    /// Input interrupted by a signal before each of its bytes.
+    struct Interrupted(std::collections::VecDeque<Option<u8>>);
+
+    impl Input for Interrupted {
+        fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+            match self.0.pop_front() {
+                Some(None) => Err(std::io::ErrorKind::Interrupted.into()),
+                Some(Some(byte)) => Ok(Some(byte)),
+                None => Ok(None),
+            }
+        }
+    }
+
+    #[test]
+    fn pause_on_interrupted_input() {
+        let ast = parser::parse("+,.").unwrap();
+        let input = Interrupted(vec![None, None, Some(7)].into());
+        let mut machine = Machine::new(16, &ast, input, Vec::new());
+
+        assert!(machine.run_until(|machine| machine.steps() > 0).unwrap());
+        assert!(machine.run_until(|_| true).unwrap());
+        assert_eq!(
+            (1, 1, 0),
+            (machine.pc(), machine.steps(), machine.input_offset())
+        );
+        assert!(machine.step().unwrap_err().is_interrupted());
+
+        machine.run().unwrap();
+        assert_eq!(&vec![7], machine.output());
+        assert_eq!(1, machine.input_offset());
+    }
+
+    #[test]
+    fn machine_runs_like_interpreter() {
+        let source = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+        let ast = crate::optimizer::shrink_calls(&parser::parse(source).unwrap());
+        let mut machine = Machine::new(16, &ast, "".as_bytes(), Vec::new());
+
+        machine.run().unwrap();
+
+        assert!(machine.is_halted());
+        assert_eq!(b"Hello World!\n", &machine.output()[..]);
+        assert!(!machine.step().unwrap());
+    }
+
+    #[test]
+    fn pause_and_resume_machine() {
+        let ast = crate::optimizer::shrink_calls(&parser::parse("++[>+<-]>.").unwrap());
+        let mut machine = Machine::new(16, &ast, "".as_bytes(), Vec::new());
+
+        assert!(machine.step().unwrap());
+        assert_eq!(
+            (1, 1, 2),
+            (machine.pc(), machine.steps(), machine.tape()[0])
+        );
+        assert!(machine.run_until(|machine| machine.pc() == 6).unwrap());
+        assert_eq!((1, 1), (machine.tape()[0], machine.tape()[1]));
+        assert!(machine.run_until(|machine| machine.pointer() == 1).unwrap());
+        assert_eq!(3, machine.pc());
+        assert!(machine.run_until(|machine| machine.pc() == 7).unwrap());
+        assert_eq!((0, 2), (machine.tape()[0], machine.tape()[1]));
+        assert!(!machine.run_until(|_| false).unwrap());
+        assert_eq!(vec![2], *machine.output());
+
+        machine.reset();
+        assert_eq!(
+            (0, 0, 0),
+            (machine.pc(), machine.steps(), machine.tape()[1])
+        );
+        machine.run().unwrap();
+        assert_eq!(vec![2, 2], *machine.output());
+    }
+
+    #[test]
+    fn step_back() {
+        let ast = crate::optimizer::shrink_calls(&parser::parse(",[>+<-]>.,.").unwrap());
+        let mut machine = Machine::new(16, &ast, "\x02ab".as_bytes(), Vec::new());
+        machine.set_recording(true);
+        machine.run().unwrap();
+        let end = machine.snapshot();
+
+        assert_eq!(
+            Some(CellWrite {
+                step: 15,
+                pc: 9,
+                previous: 2,
+                value: b'a',
+            }),
+            machine.last_write(1)
+        );
+        assert_eq!(
+            Some(CellWrite {
+                step: 11,
+                pc: 5,
+                previous: 1,
+                value: 0,
+            }),
+            machine.last_write(0)
+        );
+        assert!(machine.step_back());
+        assert!(machine.step_back());
+        assert_eq!(
+            (1, 9, 14, 1),
+            (
+                machine.pointer(),
+                machine.pc(),
+                machine.steps(),
+                machine.input_offset()
+            )
+        );
+        assert_eq!(2, machine.tape()[1]);
+        assert!(machine.run_back_until(|machine| machine.pc() == 0));
+        assert_eq!(
+            (0, 0, 0),
+            (machine.pointer(), machine.steps(), machine.input_offset())
+        );
+        assert!(!machine.step_back());
+
+        machine.run().unwrap();
+        assert_eq!(end, machine.snapshot());
+        assert_eq!(b"\x02a\x02a", &machine.output()[..]);
+    }
+
+    #[test]
+    fn record_only_when_asked() {
+        let ast = parser::parse("+").unwrap();
+        let mut machine = Machine::new(16, &ast, "".as_bytes(), Vec::new());
+
+        machine.run().unwrap();
+        assert!(!machine.step_back());
+        assert_eq!(None, machine.last_write(0));
+
+        machine.reset();
+        machine.set_recording(true);
+        machine.run().unwrap();
+        machine.set_recording(false);
+        assert!(!machine.step_back());
+    }
+
+    #[test]
+    fn run_tracks_watchpoints_only_when_set() {
+        let ast = parser::parse("+").unwrap();
+        let mut machine = Machine::new(16, &ast, "".as_bytes(), Vec::new());
+
+        machine.run().unwrap();
+        assert_eq!(None, machine.triggered());
+
+        machine.reset();
+        machine.watch(Watchpoint::Write(0));
+        machine.run().unwrap();
+        assert_eq!(
+            Some((
+                Watchpoint::Write(0),
+                CellWrite {
+                    step: 1,
+                    pc: 0,
+                    previous: 0,
+                    value: 1,
+                }
+            )),
+            machine.triggered()
+        );
+    }
+
+    #[test]
+    fn watch_cells() {
+        let ast = crate::optimizer::shrink_calls(&parser::parse("++[>+<-]>.").unwrap());
+        let mut machine = Machine::new(16, &ast, "".as_bytes(), Vec::new());
+        assert!(machine.watch(Watchpoint::Write(0)));
+        assert!(machine.watch(Watchpoint::Equals(1, 2)));
+        assert!(!machine.watch(Watchpoint::Write(0)));
+        let mut hits = Vec::new();
+
+        let stopped = machine
+            .run_watching(|_, watchpoint, write| {
+                hits.push((
+                    watchpoint,
+                    write.step,
+                    write.pc,
+                    write.previous,
+                    write.value,
+                ));
+                false
+            })
+            .unwrap();
+
+        assert!(!stopped);
+        assert_eq!(
+            vec![
+                (Watchpoint::Write(0), 1, 0, 0, 2),
+                (Watchpoint::Write(0), 6, 5, 2, 1),
+                (Watchpoint::Equals(1, 2), 9, 3, 1, 2),
+                (Watchpoint::Write(0), 11, 5, 1, 0),
+            ],
+            hits
+        );
+
+        machine.reset();
+        assert!(machine.unwatch(Watchpoint::Write(0)));
+        assert!(!machine.unwatch(Watchpoint::Write(0)));
+        assert!(machine.run_watching(|_, _, _| true).unwrap());
+        assert_eq!((4, 9), (machine.pc(), machine.steps()));
+        assert_eq!(
+            Some(Watchpoint::Equals(1, 2)),
+            machine.triggered().map(|(watchpoint, _)| watchpoint)
+        );
+        machine.step().unwrap();
+        assert_eq!(None, machine.triggered());
+    }
+
+    #[test]
+    fn failed_step_leaves_machine_untouched() {
+        let ast = parser::parse("+<").unwrap();
+        let mut machine = Machine::new(16, &ast, "".as_bytes(), Vec::new());
+        machine.step().unwrap();
+
+        let err = machine.step().unwrap_err();
+
+        assert_eq!(RuntimeError("negative memory address are invalid"), err);
+        assert_eq!((1, 1), (machine.pc(), machine.steps()));
+    }
 }
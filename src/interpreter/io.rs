@@ -0,0 +1,336 @@
+//! Input and output of the programs run by the interpreter, one byte at a time.
+//!
+//! `Bytes` reads and writes raw bytes through `std::io`, while `Text` treats each byte as the
+//! Unicode character with the same code point (Latin-1), encoded as UTF-8. `Encoded` writes
+//! with any `Encoding`, the same way as the compiled programs, and `Numbers` reads decimal
+//! numbers. Byte slices and vectors serve as in-memory buffers, and `InputFn` and `OutputFn`
+//! hand the bytes over to callbacks.
+
+use crate::encoding::Encoding;
+
+/// Source of the bytes read by `,`.
+pub trait Input {
+    /// Reads the next byte, or returns `None` once the input is over. An `Interrupted` error
+    /// leaves the input untouched, so that the read can be tried again.
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>>;
+}
+
+/// Destination of the bytes written by `.`.
+pub trait Output {
+    fn write_byte(&mut self, byte: u8) -> std::io::Result<()>;
+
+    /// Sends the bytes which may have been buffered.
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: Input + ?Sized> Input for &mut T {
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        (**self).read_byte()
+    }
+}
+
+impl<T: Input + ?Sized> Input for Box<T> {
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        (**self).read_byte()
+    }
+}
+
+impl<T: Output + ?Sized> Output for &mut T {
+    fn write_byte(&mut self, byte: u8) -> std::io::Result<()> {
+        (**self).write_byte(byte)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        (**self).flush()
+    }
+}
+
+impl<T: Output + ?Sized> Output for Box<T> {
+    fn write_byte(&mut self, byte: u8) -> std::io::Result<()> {
+        (**self).write_byte(byte)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        (**self).flush()
+    }
+}
+
+/// Input consumed from the start of the slice.
+impl Input for &[u8] {
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        match self.split_first() {
+            Some((byte, rest)) => {
+                *self = rest;
+                Ok(Some(*byte))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl Input for std::collections::VecDeque<u8> {
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        Ok(self.pop_front())
+    }
+}
+
+impl Output for Vec<u8> {
+    fn write_byte(&mut self, byte: u8) -> std::io::Result<()> {
+        self.push(byte);
+        Ok(())
+    }
+}
+
+/// Raw bytes, read from or written to a `std::io` stream as they are. A read interrupted by a
+/// signal is reported rather than retried, for the program to be paused while it waits for
+/// its input.
+pub struct Bytes<T>(pub T);
+
+impl<R: std::io::Read> Input for Bytes<R> {
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        let mut buffer = [0];
+        match self.0.read(&mut buffer)? {
+            0 => Ok(None),
+            _ => Ok(Some(buffer[0])),
+        }
+    }
+}
+
+/// Reads the next byte of a value whose first bytes were already consumed, retrying when
+/// interrupted since these bytes can't be given back.
+fn continue_reading<R: Input>(input: &mut R) -> std::io::Result<Option<u8>> {
+    loop {
+        match input.read_byte() {
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            result => return result,
+        }
+    }
+}
+
+impl<W: std::io::Write> Output for Bytes<W> {
+    fn write_byte(&mut self, byte: u8) -> std::io::Result<()> {
+        self.0.write_all(&[byte])
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// UTF-8 text, each byte standing for the character with the same code point. Characters
+/// beyond `ÿ` (U+00FF) can't be read.
+pub struct Text<T>(pub T);
+
+impl<R: std::io::Read> Input for Text<R> {
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        let mut bytes = Bytes(&mut self.0);
+        let first = match bytes.read_byte()? {
+            Some(first) => first,
+            None => return Ok(None),
+        };
+        let length = match first {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => 1,
+        };
+        let mut encoded = vec![first];
+        for _ in 1..length {
+            match continue_reading(&mut bytes)? {
+                Some(byte) => encoded.push(byte),
+                None => break,
+            }
+        }
+
+        let invalid = |message| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+        let text = std::str::from_utf8(&encoded).map_err(|_| invalid("invalid UTF-8"))?;
+        let c = text.chars().next().unwrap_or_default();
+        u8::try_from(u32::from(c))
+            .map(Some)
+            .map_err(|_| invalid("character beyond U+00FF"))
+    }
+}
+
+impl<W: std::io::Write> Output for Text<W> {
+    fn write_byte(&mut self, byte: u8) -> std::io::Result<()> {
+        write!(self.0, "{}", byte as char)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Bytes written to a `std::io` stream with an encoding.
+pub struct Encoded<T>(pub Encoding, pub T);
+
+impl<W: std::io::Write> Output for Encoded<W> {
+    fn write_byte(&mut self, byte: u8) -> std::io::Result<()> {
+        self.1.write_all(self.0.encode(byte, &mut [0; 4]))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.1.flush()
+    }
+}
+
+/// Decimal numbers from 0 to 255 separated by whitespace, each of them read as one byte.
+pub struct Numbers<T>(pub T);
+
+impl<R: std::io::Read> Input for Numbers<R> {
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        let invalid = |message| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+        let mut bytes = Bytes(&mut self.0);
+        let mut number: Option<u8> = None;
+        loop {
+            let byte = match number {
+                Some(_) => continue_reading(&mut bytes)?,
+                None => bytes.read_byte()?,
+            };
+            let byte = match byte {
+                Some(byte) => byte,
+                None => break,
+            };
+            match byte {
+                b'0'..=b'9' => {
+                    let value = number
+                        .unwrap_or(0)
+                        .checked_mul(10)
+                        .and_then(|value| value.checked_add(byte - b'0'))
+                        .ok_or_else(|| invalid("number beyond 255"))?;
+                    number = Some(value);
+                }
+                _ if byte.is_ascii_whitespace() => {
+                    if number.is_some() {
+                        break;
+                    }
+                }
+                _ => return Err(invalid("expected a decimal number")),
+            }
+        }
+
+        Ok(number)
+    }
+}
+
+/// Input given by a callback, returning `None` once the input is over.
+pub struct InputFn<F>(pub F);
+
+impl<F: FnMut() -> Option<u8>> Input for InputFn<F> {
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        Ok((self.0)())
+    }
+}
+
+/// Output handed over to a callback.
+pub struct OutputFn<F>(pub F);
+
+impl<F: FnMut(u8)> Output for OutputFn<F> {
+    fn write_byte(&mut self, byte: u8) -> std::io::Result<()> {
+        (self.0)(byte);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_bytes_and_text() {
+        let mut bytes = Bytes("é".as_bytes());
+        assert_eq!(Some(0xc3), bytes.read_byte().unwrap());
+        assert_eq!(Some(0xa9), bytes.read_byte().unwrap());
+        assert_eq!(None, bytes.read_byte().unwrap());
+
+        let mut text = Text("aé".as_bytes());
+        assert_eq!(Some(b'a'), text.read_byte().unwrap());
+        assert_eq!(Some(0xe9), text.read_byte().unwrap());
+        assert_eq!(None, text.read_byte().unwrap());
+
+        let err = Text("€".as_bytes()).read_byte().unwrap_err();
+        assert_eq!("character beyond U+00FF", err.to_string());
+        let err = Text(&[0xe9, b'a'][..]).read_byte().unwrap_err();
+        assert_eq!("invalid UTF-8", err.to_string());
+    }
+
+    #[test]
+    fn write_bytes_and_text() {
+        let mut bytes = Bytes(Vec::new());
+        let mut text = Text(Vec::new());
+        for byte in [b'a', 0xe9] {
+            bytes.write_byte(byte).unwrap();
+            text.write_byte(byte).unwrap();
+        }
+
+        assert_eq!(vec![b'a', 0xe9], bytes.0);
+        assert_eq!("aé".as_bytes(), &text.0[..]);
+
+        let mut decimal = Encoded(Encoding::Decimal, Vec::new());
+        decimal.write_byte(7).unwrap();
+        decimal.write_byte(0xe9).unwrap();
+        assert_eq!(b"7\n233\n", &decimal.1[..]);
+    }
+
+    #[test]
+    fn read_numbers() {
+        let mut numbers = Numbers(" 7\n255  0\t12".as_bytes());
+        assert_eq!(Some(7), numbers.read_byte().unwrap());
+        assert_eq!(Some(255), numbers.read_byte().unwrap());
+        assert_eq!(Some(0), numbers.read_byte().unwrap());
+        assert_eq!(Some(12), numbers.read_byte().unwrap());
+        assert_eq!(None, numbers.read_byte().unwrap());
+
+        let err = Numbers("256".as_bytes()).read_byte().unwrap_err();
+        assert_eq!("number beyond 255", err.to_string());
+        let err = Numbers("1a".as_bytes()).read_byte().unwrap_err();
+        assert_eq!("expected a decimal number", err.to_string());
+    }
+
+    /// Stream giving one byte per read, each `None` being a read interrupted by a signal.
+    struct Signaled(std::collections::VecDeque<Option<u8>>);
+
+    impl std::io::Read for Signaled {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.pop_front() {
+                Some(Some(byte)) => {
+                    buffer[0] = byte;
+                    Ok(1)
+                }
+                Some(None) => Err(std::io::ErrorKind::Interrupted.into()),
+                None => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn report_interrupted_reads() {
+        let mut bytes = Bytes(Signaled(vec![None, Some(1)].into()));
+        let err = bytes.read_byte().unwrap_err();
+        assert_eq!(std::io::ErrorKind::Interrupted, err.kind());
+        assert_eq!(Some(1), bytes.read_byte().unwrap());
+
+        // Only the reads interrupted before the number starts are reported.
+        let input = vec![Some(b' '), None, Some(b'4'), None, Some(b'2')];
+        let mut numbers = Numbers(Signaled(input.into()));
+        let err = numbers.read_byte().unwrap_err();
+        assert_eq!(std::io::ErrorKind::Interrupted, err.kind());
+        assert_eq!(Some(42), numbers.read_byte().unwrap());
+    }
+
+    #[test]
+    fn use_callbacks() {
+        let mut bytes = vec![7, 8].into_iter();
+        let mut input = InputFn(|| bytes.next());
+        let mut written = Vec::new();
+        let mut output = OutputFn(|byte| written.push(byte));
+
+        while let Some(byte) = input.read_byte().unwrap() {
+            output.write_byte(byte).unwrap();
+        }
+
+        assert_eq!(vec![7, 8], written);
+    }
+}
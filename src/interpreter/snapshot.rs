@@ -0,0 +1,294 @@
+//! State of a `Machine` saved to a file, to be resumed later.
+//!
+//! Snapshots are text files, one field per line:
+//!
+//! ```text
+//! brainfuck snapshot 1
+//! memory 4096
+//! program +2 [ >1 +1 <1 -1 ] >1 .
+//! pc 3
+//! steps 3
+//! input 0
+//! pointer 1
+//! tape 4096 02
+//! ```
+//!
+//! The program is stored as the instructions run by the machine, so a snapshot can be resumed
+//! without its source. Loops being jumps within these instructions, no loop stack is needed. The
+//! tape is written in hexadecimal, without its trailing zeros.
+
+use super::Op;
+
+const HEADER: &str = "brainfuck snapshot 1";
+
+/// Longest tape accepted when reading a snapshot, so that a corrupted length is reported instead
+/// of exhausting the memory.
+const TAPE_LIMIT: usize = 1 << 30;
+
+#[derive(Debug)]
+pub struct SnapshotError(String);
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid snapshot: {}", self.0)
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(err: std::io::Error) -> Self {
+        Self(err.to_string())
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Snapshot {
+    pub(super) default_memory: usize,
+    pub(super) program: Vec<Op>,
+    pub(super) cells: Vec<u8>,
+    pub(super) pointer: usize,
+    pub(super) pc: usize,
+    pub(super) steps: u64,
+    pub(super) input_offset: u64,
+}
+
+impl Snapshot {
+    /// Number of bytes the program read before the snapshot was taken, each of them being a
+    /// number when the input is read through `io::Numbers`.
+    pub fn input_offset(&self) -> u64 {
+        self.input_offset
+    }
+
+    pub fn write<W: std::io::Write + ?Sized>(&self, out: &mut W) -> std::io::Result<()> {
+        let program: Vec<String> = self
+            .program
+            .iter()
+            .map(|op| match op {
+                Op::MovePointerRight(n) => format!(">{}", n),
+                Op::MovePointerLeft(n) => format!("<{}", n),
+                Op::IncrementCell(n) => format!("+{}", n),
+                Op::DecrementCell(n) => format!("-{}", n),
+                Op::DisplayCell => ".".to_string(),
+                Op::ReplaceCell => ",".to_string(),
+                Op::LoopStart(_) => "[".to_string(),
+                Op::LoopEnd(_) => "]".to_string(),
+            })
+            .collect();
+        let used = self
+            .cells
+            .iter()
+            .rposition(|&cell| cell != 0)
+            .map_or(0, |last| last + 1);
+        let tape: String = self.cells[..used]
+            .iter()
+            .map(|cell| format!("{:02x}", cell))
+            .collect();
+
+        writeln!(out, "{}", HEADER)?;
+        writeln!(out, "memory {}", self.default_memory)?;
+        writeln!(out, "program {}", program.join(" "))?;
+        writeln!(out, "pc {}", self.pc)?;
+        writeln!(out, "steps {}", self.steps)?;
+        writeln!(out, "input {}", self.input_offset)?;
+        writeln!(out, "pointer {}", self.pointer)?;
+        writeln!(out, "tape {} {}", self.cells.len(), tape)
+    }
+
+    pub fn read<R: std::io::BufRead>(input: R) -> Result<Self, SnapshotError> {
+        let mut lines = input.lines();
+        let mut field = |name: &str| -> Result<String, SnapshotError> {
+            let line = lines
+                .next()
+                .transpose()?
+                .ok_or_else(|| SnapshotError(format!("missing {}", name)))?;
+            line.strip_prefix(name)
+                .and_then(|value| value.strip_prefix(' '))
+                .map(String::from)
+                .ok_or_else(|| SnapshotError(format!("expected {}, got '{}'", name, line)))
+        };
+
+        if field("brainfuck snapshot")? != "1" {
+            return Err(SnapshotError("unsupported version".to_string()));
+        }
+        let default_memory = number(&field("memory")?)?;
+        let program = parse_program(&field("program")?)?;
+        let pc = number(&field("pc")?)?;
+        let steps = number(&field("steps")?)?;
+        let input_offset = number(&field("input")?)?;
+        let pointer = number(&field("pointer")?)?;
+        let tape = field("tape")?;
+        let (length, hex) = tape.split_once(' ').unwrap_or((&tape, ""));
+        let mut cells = parse_hex(hex)?;
+        let length = number(length)?;
+        if length > TAPE_LIMIT || default_memory > TAPE_LIMIT {
+            return Err(SnapshotError(format!(
+                "tape longer than {} cells",
+                TAPE_LIMIT
+            )));
+        }
+        if cells.len() > length || pointer >= length || pc > program.len() {
+            return Err(SnapshotError("inconsistent state".to_string()));
+        }
+        cells
+            .try_reserve_exact(length - cells.len())
+            .map_err(|_| SnapshotError(format!("cannot allocate {} cells", length)))?;
+        cells.resize(length, 0);
+
+        Ok(Self {
+            default_memory,
+            program,
+            cells,
+            pointer,
+            pc,
+            steps,
+            input_offset,
+        })
+    }
+}
+
+fn number<T: std::str::FromStr>(text: &str) -> Result<T, SnapshotError> {
+    text.parse()
+        .map_err(|_| SnapshotError(format!("expected a number, got '{}'", text)))
+}
+
+fn parse_hex(text: &str) -> Result<Vec<u8>, SnapshotError> {
+    if !text.len().is_multiple_of(2) {
+        return Err(SnapshotError("truncated tape".to_string()));
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| SnapshotError("invalid tape".to_string()))
+        })
+        .collect()
+}
+
+fn parse_program(text: &str) -> Result<Vec<Op>, SnapshotError> {
+    let mut program = Vec::new();
+    let mut loops = Vec::new();
+    for token in text.split_whitespace() {
+        let (command, count) = token.split_at(token.chars().next().map_or(0, char::len_utf8));
+        let op = match (command, count) {
+            (">", n) => Op::MovePointerRight(number(n)?),
+            ("<", n) => Op::MovePointerLeft(number(n)?),
+            ("+", n) => Op::IncrementCell(number(n)?),
+            ("-", n) => Op::DecrementCell(number(n)?),
+            (".", "") => Op::DisplayCell,
+            (",", "") => Op::ReplaceCell,
+            ("[", "") => {
+                loops.push(program.len());
+                Op::LoopStart(0)
+            }
+            ("]", "") => {
+                let start = loops
+                    .pop()
+                    .ok_or_else(|| SnapshotError("unexpected end of loop".to_string()))?;
+                program[start] = Op::LoopStart(program.len());
+                Op::LoopEnd(start)
+            }
+            _ => return Err(SnapshotError(format!("unknown instruction '{}'", token))),
+        };
+        program.push(op);
+    }
+    if !loops.is_empty() {
+        return Err(SnapshotError("missing end of loop".to_string()));
+    }
+
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Machine;
+    use crate::{optimizer, parser};
+
+    #[test]
+    fn write_snapshot() {
+        let ast = optimizer::shrink_calls(&parser::parse("++[>+<-]>.").unwrap());
+        let mut machine = Machine::new(16, &ast, "".as_bytes(), Vec::new());
+        machine.run_until(|machine| machine.steps() == 3).unwrap();
+        let mut out = Vec::new();
+
+        machine.snapshot().write(&mut out).unwrap();
+
+        assert_eq!(
+            "brainfuck snapshot 1\nmemory 16\nprogram +2 [ >1 +1 <1 -1 ] >1 .\npc 3\nsteps 3\ninput 0\npointer 1\ntape 16 02\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn resume_from_snapshot() {
+        let ast = optimizer::shrink_calls(&parser::parse(",[.,]").unwrap());
+        let mut machine = Machine::new(16, &ast, "abc\0".as_bytes(), Vec::new());
+        machine
+            .run_until(|machine| machine.input_offset() == 2)
+            .unwrap();
+        let mut out = Vec::new();
+        machine.snapshot().write(&mut out).unwrap();
+
+        let snapshot = Snapshot::read(&out[..]).unwrap();
+        assert_eq!(machine.snapshot(), snapshot);
+        assert_eq!(2, snapshot.input_offset());
+        let mut resumed = Machine::from_snapshot(snapshot, "c\0".as_bytes(), Vec::new());
+        resumed.run().unwrap();
+
+        assert_eq!(b"bc", &resumed.output()[..]);
+        assert_eq!(machine.steps() + 7, resumed.steps());
+    }
+
+    #[test]
+    fn reject_invalid_snapshots() {
+        let valid = "brainfuck snapshot 1\nmemory 16\nprogram [ ]\npc 0\nsteps 0\ninput 0\npointer 0\ntape 16 \n";
+        assert!(Snapshot::read(valid.as_bytes()).is_ok());
+
+        for (snapshot, message) in [
+            ("", "missing brainfuck snapshot"),
+            ("brainfuck snapshot 2\n", "unsupported version"),
+            (
+                "brainfuck snapshot 1\nmemory x\n",
+                "expected a number, got 'x'",
+            ),
+            (
+                &valid.replace("program [ ]", "program ]"),
+                "unexpected end of loop",
+            ),
+            (
+                &valid.replace("program [ ]", "program ["),
+                "missing end of loop",
+            ),
+            (
+                &valid.replace("program [ ]", "program *"),
+                "unknown instruction '*'",
+            ),
+            (&valid.replace("tape 16 ", "tape 16 0"), "truncated tape"),
+            (
+                &valid.replace("program [ ]", "program é"),
+                "unknown instruction 'é'",
+            ),
+            (
+                &valid.replace("pointer 0", "pointer 16"),
+                "inconsistent state",
+            ),
+            (
+                &valid.replace("tape 16 ", "tape 99999999999999 "),
+                "tape longer than 1073741824 cells",
+            ),
+            (
+                &valid.replace("memory 16", "memory 99999999999999"),
+                "tape longer than 1073741824 cells",
+            ),
+            (
+                &valid.replace("pc 0", "steps 0"),
+                "expected pc, got 'steps 0'",
+            ),
+        ] {
+            let err = Snapshot::read(snapshot.as_bytes()).unwrap_err();
+            assert_eq!(format!("invalid snapshot: {}", message), err.to_string());
+        }
+    }
+}
@@ -1,5 +1,10 @@
+pub mod build;
 pub mod compiler;
+pub mod debugger;
+pub mod encoding;
+pub mod inspect;
 pub mod interpreter;
 pub mod lexer;
 pub mod optimizer;
 pub mod parser;
+pub mod repl;
@@ -1,16 +1,98 @@
-use brainfuck::{compiler, interpreter, optimizer, parser};
+use brainfuck::encoding::Encoding;
+use brainfuck::{compiler, debugger, inspect, interpreter, optimizer, parser, repl};
+use clap::builder::TypedValueParser;
 use clap::Parser;
 use rand::distributions::DistString;
 
 #[derive(clap::ValueEnum, Clone, Copy)]
-enum ExecutionMode {
-    Interpreter,
-    Compiler,
+enum Emit {
+    Source,
+    Binary,
+    Both,
+    RustLib,
+    Wat,
 }
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Interpret a program
+    Run(RunCommandArgs),
+    /// Compile a program through one of the targets
+    Build(BuildArgs),
+    /// Check that a program parses, without running it
+    Check(SourceArgs),
+    /// Print the program formatted, one loop per block of indented lines
+    Fmt(SourceArgs),
+    /// Run a program step by step, with breakpoints (help lists the commands)
+    Debug(RunArgs),
+    /// Read and run programs line by line, all of them sharing the same tape (:help lists the other commands)
+    Repl(MemoryArgs),
+    /// Print the optimized instructions of a program
+    Disasm(SourceArgs),
+    /// Print statistics about a program
+    Stats(SourceArgs),
+}
+
+#[derive(clap::Args)]
+struct SourceArgs {
+    #[arg(
+        required_unless_present = "expression",
+        long_help = "Path to the brainfuck program source code, or - to read it from STDIN"
+    )]
+    source: Option<String>,
+    #[arg(
+        short = 'e',
+        long = "expression",
+        conflicts_with = "source",
+        long_help = "Brainfuck code given on the command line instead of a source file"
+    )]
+    expression: Option<String>,
+}
+
+impl SourceArgs {
+    fn read(&self) -> Result<String, Box<dyn std::error::Error>> {
+        use std::io::Read;
+
+        match (&self.expression, self.source.as_deref()) {
+            (Some(expression), _) => Ok(expression.clone()),
+            (None, Some("-")) => {
+                let mut source = String::new();
+                std::io::stdin()
+                    .read_to_string(&mut source)
+                    .map_err(|err| format!("cannot read the program from STDIN: {}", err))?;
+                Ok(source)
+            }
+            (None, Some(path)) => std::fs::read_to_string(path)
+                .map_err(|err| format!("cannot read {}: {}", path, err).into()),
+            (None, None) => unreachable!("a source is required by the CLI"),
+        }
+    }
+
+    /// Name of the program, taken from its source file when there is one.
+    fn name(&self) -> &str {
+        match (&self.expression, self.source.as_deref()) {
+            (None, Some(path)) if path != "-" => std::path::Path::new(path)
+                .file_stem()
+                .and_then(|f| f.to_str())
+                .unwrap_or("program.out"),
+            _ => "program",
+        }
+    }
+
+    fn parse(&self) -> Result<Vec<parser::Instruction>, Box<dyn std::error::Error>> {
+        Ok(parser::parse(&self.read()?)?)
+    }
+}
+
+#[derive(clap::Args)]
+struct MemoryArgs {
     #[arg(
         short = 'm',
         long = "memory",
@@ -18,58 +100,455 @@ struct Cli {
         long_help = "Memory allocated when the brainfuck program starts. This is the initial memory but it can grow bigger if required"
     )]
     memory: usize,
+}
+
+#[derive(clap::Args)]
+struct EncodingArgs {
+    #[arg(
+        long = "encoding",
+        default_value = "raw",
+        value_parser = clap::builder::PossibleValuesParser::new(
+            Encoding::ALL.map(|encoding| encoding.name())
+        )
+        .map(|name| name.parse::<Encoding>().expect("encoding is validated by the CLI")),
+        long_help = "Define how the cells displayed by the program are written: as raw bytes, as the characters of the same code points encoded in UTF-8 (latin1), or as decimal numbers, one per line. The bf target can only write raw bytes"
+    )]
+    encoding: Encoding,
+    #[arg(
+        long = "numeric",
+        conflicts_with = "encoding",
+        long_help = "Read and write numbers instead of characters: , parses a decimal number from 0 to 255, the numbers being separated by whitespace, and . writes the cell as a decimal number, one per line. Only the rust target can compile a program reading numbers"
+    )]
+    numeric: bool,
+}
+
+impl EncodingArgs {
+    fn encoding(&self) -> Encoding {
+        if self.numeric {
+            Encoding::Decimal
+        } else {
+            self.encoding
+        }
+    }
+}
+
+#[derive(clap::Args)]
+struct RunArgs {
+    #[command(flatten)]
+    memory: MemoryArgs,
+    #[command(flatten)]
+    encoding: EncodingArgs,
+    #[arg(
+        short = 'i',
+        long = "input",
+        long_help = "File the program reads its input from instead of STDIN"
+    )]
+    input: Option<String>,
+    #[command(flatten)]
+    source: SourceArgs,
+}
+
+impl RunArgs {
+    fn input(&self) -> Result<Box<dyn interpreter::io::Input>, Box<dyn std::error::Error>> {
+        let reader: Box<dyn std::io::Read> = match &self.input {
+            Some(path) => {
+                let file = std::fs::File::open(path)
+                    .map_err(|err| format!("cannot read {}: {}", path, err))?;
+                Box::new(std::io::BufReader::new(file))
+            }
+            // Stdin is buffered already: sharing its buffer with the line editor of the debugger
+            // keeps the program from reading the commands which follow its input.
+            None => Box::new(std::io::stdin()),
+        };
+        if self.encoding.numeric {
+            Ok(Box::new(interpreter::io::Numbers(reader)))
+        } else {
+            Ok(Box::new(interpreter::io::Bytes(reader)))
+        }
+    }
+}
+
+#[derive(clap::Args)]
+#[command(mut_arg("source", |arg| arg.required_unless_present_any(["expression", "resume"])))]
+struct RunCommandArgs {
+    #[command(flatten)]
+    run: RunArgs,
+    #[arg(
+        long = "snapshot-on-signal",
+        value_name = "FILE",
+        long_help = "Write the state of the program to this file when receiving SIGINT or SIGTERM, before stopping, or SIGUSR1, before going on"
+    )]
+    snapshot_on_signal: Option<String>,
+    #[arg(
+        long = "resume",
+        value_name = "FILE",
+        conflicts_with_all = ["source", "expression"],
+        long_help = "Resume the program saved in this snapshot instead of starting a source. The input is expected to be given again from its start, the bytes already read by the program being skipped"
+    )]
+    resume: Option<String>,
+}
+
+#[derive(clap::Args)]
+#[command(mut_arg("source", |arg| arg.required_unless_present_any(["expression", "list_targets"])))]
+struct BuildArgs {
+    #[command(flatten)]
+    memory: MemoryArgs,
+    #[command(flatten)]
+    encoding: EncodingArgs,
+    #[arg(
+        short = 't',
+        long = "target",
+        default_value = "rust",
+        value_parser = clap::builder::PossibleValuesParser::new(
+            compiler::backends().iter().map(|backend| backend.name())
+        ),
+        long_help = "Define which language the program is compiled through"
+    )]
+    target: String,
     #[arg(
         value_enum,
-        short = 'x',
-        long = "execution",
-        default_value_t = ExecutionMode::Interpreter,
-        long_help = "Define how the current program should behave"
+        long = "emit",
+        default_value_t = Emit::Binary,
+        long_help = "Define what the compiler writes in the output directory: the generated source, the executable built from it, or both. Targets which don't build any executable always write their generated source. The rust target can also write a library crate exposing a run function instead of an executable (rust-lib), and the wasm target the text format of its module (wat)"
+    )]
+    emit: Emit,
+    #[arg(
+        long = "keep-source",
+        long_help = "Keep the generated source in the temporary directory instead of deleting it once compiled"
+    )]
+    keep_source: bool,
+    #[arg(
+        long = "rustc",
+        long_help = "Path to the rustc binary used by the rust target. It defaults to the RUSTC environment variable, or to the rustc found in the PATH"
+    )]
+    rustc: Option<String>,
+    #[arg(
+        long = "opt-level",
+        long_help = "Optimization level given to rustc (-C opt-level) by the rust target"
+    )]
+    opt_level: Option<String>,
+    #[arg(
+        long = "target-triple",
+        long_help = "Target triple given to rustc by the rust target"
     )]
-    execution: ExecutionMode,
+    target_triple: Option<String>,
+    #[arg(
+        long = "lto",
+        long_help = "Enable link-time optimizations in rustc for the rust target"
+    )]
+    lto: bool,
+    #[arg(
+        long = "list-targets",
+        conflicts_with_all = ["source", "expression"],
+        long_help = "List the targets the program can be compiled to and exit"
+    )]
+    list_targets: bool,
     #[arg(
         short = 'o',
         long = "output-dir",
         default_value = ".",
-        long_help = "Define where the compiled program should be generated. It's only used when using compiler execution mode"
+        long_help = "Define where the compiled program should be generated"
     )]
     output_folder: String,
-    #[arg(long_help = "Path to the brainfuck program source code")]
-    source: String,
-}
-
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Cli::parse();
-    let stdin = std::io::stdin();
-    let mut reader = std::io::BufReader::new(stdin);
-    let source = std::fs::read_to_string(&args.source).expect("failed to read source file");
-    let ast = optimizer::shrink_calls(&parser::parse(&source)?);
-
-    match args.execution {
-        ExecutionMode::Interpreter => {
-            let mut out = std::io::stdout();
-            interpreter::run(args.memory, &ast, &mut reader, &mut out)?;
-        }
-        ExecutionMode::Compiler => {
-            let tmpdir_path = std::env::temp_dir();
-            let tmpdir = tmpdir_path
-                .to_str()
-                .expect("failed to get the TMP directory");
-            let random =
-                rand::distributions::Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
-            let tmpfile = format!("{}/brainfuck-program-{}.rs", tmpdir, random);
-            {
-                let mut out = std::fs::File::create(&tmpfile)?;
-                compiler::rust::generate(args.memory, &ast, &mut out)?;
+    #[command(flatten)]
+    source: SourceArgs,
+}
+
+fn main() -> std::process::ExitCode {
+    if let Err(err) = run(Cli::parse()) {
+        eprintln!("Error: {}", err);
+        return std::process::ExitCode::FAILURE;
+    }
+
+    std::process::ExitCode::SUCCESS
+}
+
+fn run(args: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    match args.command {
+        Command::Run(args) => run_program(args)?,
+        Command::Build(args) => build(args)?,
+        Command::Check(args) => {
+            args.parse()?;
+        }
+        Command::Fmt(args) => compiler::bf::format(&args.parse()?, &mut std::io::stdout())?,
+        Command::Debug(args) => debug(args)?,
+        Command::Repl(args) => repl(args)?,
+        Command::Disasm(args) => inspect::disassemble(
+            &optimizer::shrink_calls(&args.parse()?),
+            &mut std::io::stdout(),
+        )?,
+        Command::Stats(args) => {
+            let ast = args.parse()?;
+            let raw = inspect::Statistics::of(&ast);
+            let optimized = inspect::Statistics::of(&optimizer::shrink_calls(&ast));
+            println!("commands:               {}", raw.commands);
+            println!("optimized instructions: {}", optimized.instructions);
+            println!("loops:                  {}", raw.loops);
+            println!("maximum loop depth:     {}", raw.max_depth);
+            println!("inputs:                 {}", raw.inputs);
+            println!("outputs:                {}", raw.outputs);
+        }
+    }
+
+    Ok(())
+}
+
+fn run_program(args: RunCommandArgs) -> Result<(), Box<dyn std::error::Error>> {
+    use interpreter::io::{Encoded, Input, Output};
+
+    let mut input = args.run.input()?;
+    let mut out = Encoded(args.run.encoding.encoding(), std::io::stdout());
+    let mut machine = match &args.resume {
+        Some(path) => {
+            let file = std::fs::File::open(path)
+                .map_err(|err| format!("cannot read {}: {}", path, err))?;
+            let snapshot = interpreter::snapshot::Snapshot::read(std::io::BufReader::new(file))?;
+            for _ in 0..snapshot.input_offset() {
+                if input.read_byte()?.is_none() {
+                    return Err("input ended before the snapshot's input offset".into());
+                }
             }
-            let program_name = std::path::Path::new(&args.source)
-                .file_stem()
-                .and_then(|f| f.to_str())
-                .unwrap_or("program.out");
-            let outputfile = format!("{}/{}", args.output_folder, program_name);
-            compiler::rust::compile(&tmpfile, &outputfile)?;
-            std::fs::remove_file(tmpfile).expect("failed to remove temporary file");
+            interpreter::Machine::from_snapshot(snapshot, input, out)
+        }
+        None => {
+            let ast = optimizer::shrink_calls(&args.run.source.parse()?);
+            if args.snapshot_on_signal.is_none() {
+                interpreter::run(args.run.memory.memory, &ast, &mut input, &mut out)?;
+                return Ok(());
+            }
+            interpreter::Machine::new(args.run.memory.memory, &ast, input, out)
+        }
+    };
+
+    let path = match &args.snapshot_on_signal {
+        Some(path) => path,
+        None => return Ok(machine.run()?),
+    };
+    signals::install()?;
+    while machine.run_until(|_| signals::received())? {
+        let resume = signals::take();
+        machine.output_mut().flush()?;
+        let tmpfile = format!("{}.tmp", path);
+        machine
+            .snapshot()
+            .write(&mut std::io::BufWriter::new(std::fs::File::create(
+                &tmpfile,
+            )?))?;
+        std::fs::rename(&tmpfile, path)?;
+        eprintln!("snapshot written to {}", path);
+        if !resume {
+            return Err("interrupted".into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Signals asking for a snapshot of the running program.
+#[cfg(unix)]
+mod signals {
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+    extern "C" fn record(signal: libc::c_int) {
+        RECEIVED.store(signal, Ordering::SeqCst);
+    }
+
+    /// Installs the handlers without `SA_RESTART`, so that a program waiting for its input is
+    /// interrupted as well.
+    pub fn install() -> Result<(), &'static str> {
+        for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGUSR1] {
+            // SAFETY: the handler only stores the signal in an atomic, and the action is fully
+            // initialized before being installed.
+            let result = unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = record as *const () as libc::sighandler_t;
+                action.sa_flags = 0;
+                libc::sigemptyset(&mut action.sa_mask);
+                libc::sigaction(signal, &action, std::ptr::null_mut())
+            };
+            if result != 0 {
+                return Err("cannot handle signals");
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn received() -> bool {
+        RECEIVED.load(Ordering::SeqCst) != 0
+    }
+
+    /// Forgets the signal received, returning whether the program should go on.
+    pub fn take() -> bool {
+        RECEIVED.swap(0, Ordering::SeqCst) == libc::SIGUSR1
+    }
+}
+
+#[cfg(not(unix))]
+mod signals {
+    pub fn install() -> Result<(), &'static str> {
+        Err("snapshots on signals are only supported on Unix")
+    }
+
+    pub fn received() -> bool {
+        false
+    }
+
+    pub fn take() -> bool {
+        true
+    }
+}
+
+fn build(args: BuildArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if args.list_targets {
+        list_targets();
+        return Ok(());
+    }
+
+    let ast = optimizer::shrink_calls(&args.source.parse()?);
+    let program_name = args.source.name();
+    let input_format = if args.encoding.numeric {
+        if args.target != "rust" {
+            return Err("only the rust target can compile a program reading numbers".into());
+        }
+        compiler::rust::InputFormat::Numbers
+    } else {
+        compiler::rust::InputFormat::Bytes
+    };
+    if let Emit::RustLib = args.emit {
+        if args.target != "rust" {
+            return Err("only the rust target can emit a library crate".into());
+        }
+        let dir = std::path::Path::new(&args.output_folder).join(program_name);
+        compiler::rust::write_crate(
+            args.memory.memory,
+            args.encoding.encoding(),
+            input_format,
+            &ast,
+            program_name,
+            &dir,
+        )?;
+        return Ok(());
+    }
+    if let Emit::Wat = args.emit {
+        if args.target != "wasm" {
+            return Err("only the wasm target can emit the text format".into());
+        }
+        let mut out =
+            std::fs::File::create(format!("{}/{}.wat", args.output_folder, program_name))?;
+        compiler::wasm::generate_text(
+            args.memory.memory,
+            args.encoding.encoding(),
+            &ast,
+            &mut out,
+        )?;
+        return Ok(());
+    }
+
+    let backend: Box<dyn compiler::Backend> = if args.target == "rust" {
+        let defaults = compiler::rust::Options::default();
+        Box::new(
+            compiler::rust::RustBackend::new(compiler::rust::Options {
+                rustc: args.rustc.unwrap_or(defaults.rustc),
+                opt_level: args.opt_level,
+                target: args.target_triple,
+                lto: args.lto,
+            })
+            .input_format(input_format),
+        )
+    } else {
+        compiler::backend(&args.target).expect("target is validated by the CLI")
+    };
+    let tmpdir_path = std::env::temp_dir();
+    let tmpdir = tmpdir_path
+        .to_str()
+        .expect("failed to get the TMP directory");
+    let random = rand::distributions::Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+    let tmpfile = format!(
+        "{}/brainfuck-program-{}.{}",
+        tmpdir,
+        random,
+        backend.extension()
+    );
+    // The temporary source is cleaned up even when generating or building fails.
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        {
+            let mut out = std::fs::File::create(&tmpfile)?;
+            backend.generate(args.memory.memory, args.encoding.encoding(), &ast, &mut out)?;
+        }
+        let outputfile = format!("{}/{}", args.output_folder, program_name);
+        let emit_source =
+            !backend.builds_executable() || matches!(args.emit, Emit::Source | Emit::Both);
+        let emit_binary =
+            backend.builds_executable() && matches!(args.emit, Emit::Binary | Emit::Both);
+        if emit_source {
+            std::fs::copy(&tmpfile, format!("{}.{}", outputfile, backend.extension()))?;
+        }
+        if emit_binary {
+            backend.build(&tmpfile, &outputfile)?;
+        }
+
+        Ok(())
+    })();
+    if args.keep_source {
+        eprintln!("generated source kept in {}", tmpfile);
+    } else if std::path::Path::new(&tmpfile).exists() {
+        std::fs::remove_file(tmpfile).expect("failed to remove temporary file");
+    }
+
+    result
+}
+
+fn debug(args: RunArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let ast = optimizer::shrink_calls(&args.source.parse()?);
+    let mut debugger = debugger::Debugger::new(
+        args.memory.memory,
+        &ast,
+        args.input()?,
+        interpreter::io::Encoded(args.encoding.encoding(), std::io::stdout()),
+    );
+    let mut editor = repl::editor::Editor::new();
+    let mut out = std::io::stderr();
+    debugger.location(&mut out)?;
+    while let Some(line) = editor.read_line("(bf) ")? {
+        match debugger.eval(&line, &mut out) {
+            Ok(repl::Status::Continue) => {}
+            Ok(repl::Status::Exit) => break,
+            Err(err) => eprintln!("Error: {}", err),
         }
     }
 
     Ok(())
 }
+
+fn repl(args: MemoryArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut repl = repl::Repl::new(args.memory);
+    let mut editor = repl::editor::Editor::new();
+    let mut out = std::io::stdout();
+    while let Some(line) = editor.read_line("> ")? {
+        match repl.eval(&line, &mut std::io::stdin().lock(), &mut out) {
+            Ok(repl::Status::Continue) => {}
+            Ok(repl::Status::Exit) => break,
+            Err(err) => eprintln!("Error: {}", err),
+        }
+    }
+
+    Ok(())
+}
+
+fn list_targets() {
+    for backend in compiler::backends() {
+        let toolchain = match backend.toolchain() {
+            [] => String::new(),
+            programs => format!(" (requires {})", programs.join(", ")),
+        };
+        println!(
+            "{:<8}{}{}",
+            backend.name(),
+            backend.description(),
+            toolchain
+        );
+    }
+}
@@ -47,6 +47,86 @@ pub fn shrink_calls(ast: &[parser::Instruction]) -> Vec<parser::Instruction> {
     optimized
 }
 
+/// Merges adjacent pointer moves and cell updates into their net effect, removing them
+/// entirely when they cancel each other out (e.g. `+-` or `<>`).
+pub fn cancel_opposites(ast: &[parser::Instruction]) -> Vec<parser::Instruction> {
+    let mut optimized: Vec<parser::Instruction> = Vec::new();
+    for instruction in ast {
+        let pointer = pointer_offset(instruction);
+        let cell = cell_offset(instruction);
+        if let (Some(offset), Some(previous)) = (pointer, optimized.last().and_then(pointer_offset))
+        {
+            optimized.pop();
+            match previous + offset {
+                0 => {}
+                n if n > 0 => optimized.push(parser::Instruction::MovePointerRight(n as usize)),
+                n => optimized.push(parser::Instruction::MovePointerLeft(n.unsigned_abs())),
+            }
+        } else if let (Some(offset), Some(previous)) =
+            (cell, optimized.last().and_then(cell_offset))
+        {
+            optimized.pop();
+            match previous.wrapping_add(offset) {
+                0 => {}
+                n if n <= 128 => optimized.push(parser::Instruction::IncrementCell(n)),
+                n => optimized.push(parser::Instruction::DecrementCell(n.wrapping_neg())),
+            }
+        } else if let parser::Instruction::While(sub_ast) = instruction {
+            optimized.push(parser::Instruction::While(cancel_opposites(sub_ast)))
+        } else {
+            optimized.push(instruction.clone())
+        }
+    }
+
+    optimized
+}
+
+fn pointer_offset(instruction: &parser::Instruction) -> Option<isize> {
+    match instruction {
+        parser::Instruction::MovePointerRight(n) => Some(*n as isize),
+        parser::Instruction::MovePointerLeft(n) => Some(-(*n as isize)),
+        _ => None,
+    }
+}
+
+fn cell_offset(instruction: &parser::Instruction) -> Option<u8> {
+    match instruction {
+        parser::Instruction::IncrementCell(n) => Some(*n),
+        parser::Instruction::DecrementCell(n) => Some(n.wrapping_neg()),
+        _ => None,
+    }
+}
+
+/// Removes the loops which can never be entered: the ones at the beginning of the program,
+/// where every cell is still zero, and the ones right after another loop, which only exits
+/// when the current cell is zero.
+pub fn remove_dead_loops(ast: &[parser::Instruction]) -> Vec<parser::Instruction> {
+    let start = ast
+        .iter()
+        .position(|instruction| !matches!(instruction, parser::Instruction::While(_)))
+        .unwrap_or(ast.len());
+
+    remove_loops_after_loops(&ast[start..])
+}
+
+fn remove_loops_after_loops(ast: &[parser::Instruction]) -> Vec<parser::Instruction> {
+    let mut optimized: Vec<parser::Instruction> = Vec::new();
+    for instruction in ast {
+        if let parser::Instruction::While(sub_ast) = instruction {
+            if let Some(parser::Instruction::While(_)) = optimized.last() {
+                continue;
+            }
+            optimized.push(parser::Instruction::While(remove_loops_after_loops(
+                sub_ast,
+            )))
+        } else {
+            optimized.push(instruction.clone())
+        }
+    }
+
+    optimized
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -91,4 +171,55 @@ mod tests {
 
         assert_eq!(output, shrink_calls(&input))
     }
+
+    #[test]
+    fn cancelled() {
+        let input = vec![
+            parser::Instruction::IncrementCell(3),
+            parser::Instruction::DecrementCell(3),
+            parser::Instruction::MovePointerRight(2),
+            parser::Instruction::While(vec![
+                parser::Instruction::MovePointerLeft(1),
+                parser::Instruction::MovePointerRight(1),
+                parser::Instruction::DecrementCell(1),
+            ]),
+            parser::Instruction::MovePointerLeft(3),
+            parser::Instruction::DecrementCell(2),
+            parser::Instruction::IncrementCell(1),
+            parser::Instruction::IncrementCell(255),
+        ];
+
+        let output = vec![
+            parser::Instruction::MovePointerRight(2),
+            parser::Instruction::While(vec![parser::Instruction::DecrementCell(1)]),
+            parser::Instruction::MovePointerLeft(3),
+            parser::Instruction::DecrementCell(2),
+        ];
+
+        assert_eq!(output, cancel_opposites(&input))
+    }
+
+    #[test]
+    fn dead_loops_removed() {
+        let input = vec![
+            parser::Instruction::While(vec![parser::Instruction::DisplayCell]),
+            parser::Instruction::IncrementCell(1),
+            parser::Instruction::While(vec![
+                parser::Instruction::While(vec![parser::Instruction::DecrementCell(1)]),
+                parser::Instruction::While(vec![parser::Instruction::IncrementCell(1)]),
+            ]),
+            parser::Instruction::While(vec![parser::Instruction::DisplayCell]),
+            parser::Instruction::DisplayCell,
+        ];
+
+        let output = vec![
+            parser::Instruction::IncrementCell(1),
+            parser::Instruction::While(vec![parser::Instruction::While(vec![
+                parser::Instruction::DecrementCell(1),
+            ])]),
+            parser::Instruction::DisplayCell,
+        ];
+
+        assert_eq!(output, remove_dead_loops(&input))
+    }
 }
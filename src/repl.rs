@@ -0,0 +1,274 @@
+use crate::{inspect, interpreter, optimizer, parser};
+
+pub mod editor;
+
+/// Number of states kept to be restored by `:undo`.
+const UNDO_LIMIT: usize = 64;
+
+const HELP: &str = "\
+:tape         print the tape and the pointer
+:ptr          print the pointer
+:reset        clear the tape and move the pointer back to the first cell
+:load <file>  run a program from a file
+:undo         restore the tape as it was before the last change
+:help         print this message
+:quit         leave the REPL
+";
+
+#[derive(Debug)]
+pub struct ReplError(String);
+
+impl std::fmt::Display for ReplError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ReplError {}
+
+impl From<std::io::Error> for ReplError {
+    fn from(err: std::io::Error) -> Self {
+        Self(err.to_string())
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Status {
+    Continue,
+    Exit,
+}
+
+/// Runs lines of brainfuck one after another against the same tape.
+pub struct Repl {
+    default_memory: usize,
+    memory: interpreter::Memory,
+    history: Vec<interpreter::Memory>,
+}
+
+impl Repl {
+    pub fn new(default_memory: usize) -> Self {
+        Self {
+            default_memory,
+            memory: interpreter::Memory::new(default_memory),
+            history: Vec::new(),
+        }
+    }
+
+    pub fn memory(&self) -> &interpreter::Memory {
+        &self.memory
+    }
+
+    /// Evaluates a line, which is either brainfuck code or a meta-command starting with `:`.
+    /// The output ends with a new line whenever something was written, so the next prompt starts
+    /// on its own line.
+    pub fn eval<R: std::io::BufRead, W: std::io::Write>(
+        &mut self,
+        line: &str,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<Status, ReplError> {
+        use std::io::Write;
+
+        let mut output = LineWriter {
+            inner: output,
+            last: None,
+        };
+        let result = self.do_eval(line.trim(), input, &mut output);
+        if output.last.is_some_and(|last| last != b'\n') {
+            writeln!(output)?;
+        }
+        output.flush()?;
+
+        result
+    }
+
+    fn do_eval<R: std::io::BufRead, W: std::io::Write>(
+        &mut self,
+        line: &str,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<Status, ReplError> {
+        let (command, argument) = match line.strip_prefix(':') {
+            Some(meta) => meta
+                .split_once(char::is_whitespace)
+                .map_or((meta, ""), |(command, argument)| (command, argument.trim())),
+            None => {
+                self.execute(line, input, output)?;
+                return Ok(Status::Continue);
+            }
+        };
+
+        match command {
+            "tape" => inspect::dump_tape(self.memory.cells(), self.memory.pointer(), output)?,
+            "ptr" => writeln!(output, "pointer: {}", self.memory.pointer())?,
+            "reset" => {
+                self.save();
+                self.memory = interpreter::Memory::new(self.default_memory);
+            }
+            "load" if !argument.is_empty() => {
+                let source = std::fs::read_to_string(argument)
+                    .map_err(|err| ReplError(format!("cannot read {}: {}", argument, err)))?;
+                self.execute(&source, input, output)?;
+            }
+            "load" => return Err(ReplError("usage: :load <file>".to_string())),
+            "undo" => {
+                self.memory = self
+                    .history
+                    .pop()
+                    .ok_or_else(|| ReplError("nothing to undo".to_string()))?;
+            }
+            "help" => write!(output, "{}", HELP)?,
+            "quit" => return Ok(Status::Exit),
+            _ => {
+                return Err(ReplError(format!(
+                    "unknown command :{}, try :help",
+                    command
+                )))
+            }
+        }
+
+        Ok(Status::Continue)
+    }
+
+    fn execute<R: std::io::BufRead, W: std::io::Write>(
+        &mut self,
+        source: &str,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<(), ReplError> {
+        let ast = parser::parse(source).map_err(|err| ReplError(err.to_string()))?;
+        if ast.is_empty() {
+            return Ok(());
+        }
+        // The state is saved even if the program fails, so its partial changes can be undone.
+        self.save();
+        // The output is written as text, for the terminal to show the characters.
+        interpreter::execute(
+            &mut self.memory,
+            &optimizer::shrink_calls(&ast),
+            &mut interpreter::io::Bytes(input),
+            &mut interpreter::io::Text(output),
+        )
+        .map_err(|err| ReplError(err.to_string()))
+    }
+
+    fn save(&mut self) {
+        if self.history.len() == UNDO_LIMIT {
+            self.history.remove(0);
+        }
+        self.history.push(self.memory.clone());
+    }
+}
+
+/// Remembers the last byte written, to tell whether the output ends with a new line.
+struct LineWriter<'a, W> {
+    inner: &'a mut W,
+    last: Option<u8>,
+}
+
+impl<W: std::io::Write> std::io::Write for LineWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if written > 0 {
+            self.last = Some(buf[written - 1]);
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(repl: &mut Repl, line: &str) -> Result<String, ReplError> {
+        let mut output = Vec::new();
+        repl.eval(line, &mut "ab".as_bytes(), &mut output)?;
+
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn keep_tape_between_lines() {
+        let mut repl = Repl::new(16);
+
+        assert_eq!("", eval(&mut repl, "++++++++[>++++++++<-]").unwrap());
+        assert_eq!("A\n", eval(&mut repl, ">+.").unwrap());
+        assert_eq!("B\n", eval(&mut repl, "+.").unwrap());
+        assert_eq!("pointer: 1\n", eval(&mut repl, ":ptr").unwrap());
+        assert_eq!(
+            "pointer: 1\n0000  00 [42]\n",
+            eval(&mut repl, ":tape").unwrap()
+        );
+        assert_eq!("a\n", eval(&mut repl, ",.").unwrap());
+    }
+
+    #[test]
+    fn undo_and_reset() {
+        let mut repl = Repl::new(16);
+        eval(&mut repl, "+++>").unwrap();
+        eval(&mut repl, "++").unwrap();
+
+        eval(&mut repl, ":undo").unwrap();
+        assert_eq!(&[3, 0], &repl.memory().cells()[..2]);
+        assert_eq!(1, repl.memory().pointer());
+
+        eval(&mut repl, ":reset").unwrap();
+        assert_eq!(0, repl.memory().pointer());
+        eval(&mut repl, ":undo").unwrap();
+        assert_eq!(1, repl.memory().pointer());
+
+        eval(&mut repl, ":undo").unwrap();
+        let err = eval(&mut repl, ":undo").unwrap_err();
+        assert_eq!("nothing to undo", err.to_string());
+    }
+
+    #[test]
+    fn undo_failed_line() {
+        let mut repl = Repl::new(16);
+
+        let err = eval(&mut repl, "+++<").unwrap_err();
+        assert_eq!(
+            "failed during execution: negative memory address are invalid",
+            err.to_string()
+        );
+        assert_eq!(3, repl.memory().cells()[0]);
+
+        eval(&mut repl, ":undo").unwrap();
+        assert_eq!(0, repl.memory().cells()[0]);
+    }
+
+    #[test]
+    fn load_file() {
+        let mut repl = Repl::new(16);
+
+        let output = eval(&mut repl, ":load examples/hello_world.brainfuck").unwrap();
+        assert_eq!("Hello World!\n", output);
+
+        let err = eval(&mut repl, ":load").unwrap_err();
+        assert_eq!("usage: :load <file>", err.to_string());
+    }
+
+    #[test]
+    fn meta_commands() {
+        let mut repl = Repl::new(16);
+
+        assert_eq!(HELP, eval(&mut repl, ":help").unwrap());
+        let err = eval(&mut repl, ":tap").unwrap_err();
+        assert_eq!("unknown command :tap, try :help", err.to_string());
+        let err = eval(&mut repl, "[").unwrap_err();
+        assert_eq!(
+            "failed to parse source code: missing closing bracket",
+            err.to_string()
+        );
+        let mut output = Vec::new();
+        assert_eq!(
+            Status::Exit,
+            repl.eval(":quit", &mut "".as_bytes(), &mut output).unwrap()
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! Minimal line editor: cursor movements, deletions and history, for terminals understanding
+//! ANSI escape sequences. When STDIN is not a terminal, lines are read as they come.
+
+use std::io::{Read, Write};
+
+const CTRL_A: u8 = 1;
+const CTRL_C: u8 = 3;
+const CTRL_D: u8 = 4;
+const CTRL_E: u8 = 5;
+const CTRL_K: u8 = 11;
+const CTRL_U: u8 = 21;
+const BACKSPACE: u8 = 8;
+const ESCAPE: u8 = 27;
+const DELETE: u8 = 127;
+
+pub struct Editor {
+    history: Vec<String>,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Self {
+            history: Vec::new(),
+        }
+    }
+
+    /// Reads a line from STDIN after printing the prompt on STDERR, or returns `None` once the
+    /// input is over.
+    pub fn read_line(&mut self, prompt: &str) -> std::io::Result<Option<String>> {
+        match terminal::RawMode::enable() {
+            Some(_raw_mode) => {
+                self.edit_line(prompt, &mut std::io::stdin(), &mut std::io::stderr())
+            }
+            None => {
+                eprint!("{}", prompt);
+                let mut line = String::new();
+                if std::io::stdin().read_line(&mut line)? == 0 {
+                    eprintln!();
+                    return Ok(None);
+                }
+                Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()))
+            }
+        }
+    }
+
+    /// Edits a line from keys read one byte at a time, the terminal being in raw mode.
+    fn edit_line<R: Read, W: Write>(
+        &mut self,
+        prompt: &str,
+        input: &mut R,
+        output: &mut W,
+    ) -> std::io::Result<Option<String>> {
+        let mut line: Vec<char> = Vec::new();
+        let mut cursor = 0;
+        // Position in the history, the line being edited coming after the last entry.
+        let mut entry = self.history.len();
+        write!(output, "{}", prompt)?;
+        output.flush()?;
+
+        loop {
+            let key = match next_byte(input)? {
+                Some(key) => key,
+                None if line.is_empty() => return Ok(None),
+                None => b'\r',
+            };
+            match key {
+                b'\r' | b'\n' => {
+                    write!(output, "\r\n")?;
+                    let line: String = line.into_iter().collect();
+                    if !line.trim().is_empty() && self.history.last() != Some(&line) {
+                        self.history.push(line.clone());
+                    }
+                    return Ok(Some(line));
+                }
+                CTRL_C => {
+                    write!(output, "^C\r\n")?;
+                    return Ok(Some(String::new()));
+                }
+                CTRL_D if line.is_empty() => {
+                    write!(output, "\r\n")?;
+                    return Ok(None);
+                }
+                CTRL_D if cursor < line.len() => {
+                    line.remove(cursor);
+                }
+                CTRL_A => cursor = 0,
+                CTRL_E => cursor = line.len(),
+                CTRL_K => line.truncate(cursor),
+                CTRL_U => {
+                    line.drain(..cursor);
+                    cursor = 0;
+                }
+                BACKSPACE | DELETE if cursor > 0 => {
+                    cursor -= 1;
+                    line.remove(cursor);
+                }
+                BACKSPACE | DELETE => {}
+                ESCAPE => {
+                    if next_byte(input)? != Some(b'[') {
+                        continue;
+                    }
+                    match next_byte(input)? {
+                        Some(b'A') if entry > 0 => {
+                            entry -= 1;
+                            line = self.history[entry].chars().collect();
+                            cursor = line.len();
+                        }
+                        Some(b'B') if entry < self.history.len() => {
+                            entry += 1;
+                            line = self
+                                .history
+                                .get(entry)
+                                .map(|line| line.chars().collect())
+                                .unwrap_or_default();
+                            cursor = line.len();
+                        }
+                        Some(b'C') => cursor = (cursor + 1).min(line.len()),
+                        Some(b'D') => cursor = cursor.saturating_sub(1),
+                        Some(b'H') => cursor = 0,
+                        Some(b'F') => cursor = line.len(),
+                        // Delete key, sent as `ESC [ 3 ~`.
+                        Some(b'3') if next_byte(input)? == Some(b'~') && cursor < line.len() => {
+                            line.remove(cursor);
+                        }
+                        _ => {}
+                    }
+                }
+                key if key >= b' ' => {
+                    let c = next_char(key, input)?;
+                    line.insert(cursor, c);
+                    cursor += 1;
+                }
+                _ => {}
+            }
+
+            let text: String = line.iter().collect();
+            write!(output, "\r\x1b[K{}{}", prompt, text)?;
+            if cursor < line.len() {
+                write!(output, "\x1b[{}D", line.len() - cursor)?;
+            }
+            output.flush()?;
+        }
+    }
+}
+
+fn next_byte<R: Read>(input: &mut R) -> std::io::Result<Option<u8>> {
+    let mut buffer = [0];
+    match input.read(&mut buffer)? {
+        0 => Ok(None),
+        _ => Ok(Some(buffer[0])),
+    }
+}
+
+/// Decodes the UTF-8 character starting with `first`, invalid sequences being replaced.
+fn next_char<R: Read>(first: u8, input: &mut R) -> std::io::Result<char> {
+    let length = match first {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    };
+    let mut bytes = vec![first];
+    for _ in 1..length {
+        match next_byte(input)? {
+            Some(byte) => bytes.push(byte),
+            None => break,
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&bytes)
+        .chars()
+        .next()
+        .unwrap_or(char::REPLACEMENT_CHARACTER))
+}
+
+#[cfg(unix)]
+mod terminal {
+    /// Puts the terminal in raw mode until dropped.
+    pub struct RawMode(libc::termios);
+
+    impl RawMode {
+        /// Returns `None` if STDIN is not a terminal.
+        pub fn enable() -> Option<Self> {
+            // SAFETY: termios is a plain C struct filled by tcgetattr before being used.
+            unsafe {
+                if libc::isatty(libc::STDIN_FILENO) != 1 {
+                    return None;
+                }
+                let mut original: libc::termios = std::mem::zeroed();
+                if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                    return None;
+                }
+                let mut raw = original;
+                raw.c_iflag &= !(libc::ICRNL | libc::IXON);
+                raw.c_lflag &= !(libc::ECHO | libc::ICANON | libc::ISIG | libc::IEXTEN);
+                if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &raw) != 0 {
+                    return None;
+                }
+
+                Some(Self(original))
+            }
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            // SAFETY: the attributes were returned by tcgetattr.
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.0);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod terminal {
+    pub struct RawMode;
+
+    impl RawMode {
+        pub fn enable() -> Option<Self> {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(editor: &mut Editor, keys: &[u8]) -> Option<String> {
+        editor
+            .edit_line("> ", &mut &keys[..], &mut Vec::new())
+            .unwrap()
+    }
+
+    #[test]
+    fn edit_line() {
+        let mut editor = Editor::new();
+
+        assert_eq!(Some("+-.".to_string()), edit(&mut editor, b"+-.\r"));
+        assert_eq!(
+            Some("+.".to_string()),
+            edit(&mut editor, b"+-.\x1b[D\x08\r")
+        );
+        assert_eq!(Some(">+".to_string()), edit(&mut editor, b"+\x01>\r"));
+        assert_eq!(
+            Some("[é]".to_string()),
+            edit(&mut editor, "[]\x1b[Dé\r".as_bytes())
+        );
+        assert_eq!(
+            Some("+".to_string()),
+            edit(&mut editor, b"+>>\x1b[D\x1b[D\x0b\r")
+        );
+        assert_eq!(Some(">".to_string()), edit(&mut editor, b"+>\x1b[D\x15\r"));
+        assert_eq!(Some("".to_string()), edit(&mut editor, b"+\x03"));
+        assert_eq!(None, edit(&mut editor, b"\x04"));
+        assert_eq!(None, edit(&mut editor, b""));
+    }
+
+    #[test]
+    fn browse_history() {
+        let mut editor = Editor::new();
+        edit(&mut editor, b"+\r");
+        edit(&mut editor, b"-\r");
+
+        assert_eq!(Some("-".to_string()), edit(&mut editor, b"\x1b[A\r"));
+        assert_eq!(Some("+".to_string()), edit(&mut editor, b"\x1b[A\x1b[A\r"));
+        assert_eq!(Some(">".to_string()), edit(&mut editor, b"\x1b[A\x1b[B>\r"));
+        assert_eq!(vec!["+", "-", "+", ">"], editor.history);
+    }
+}